@@ -1,37 +1,284 @@
 use macroquad::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Write;
 
-#[derive(Clone, PartialEq)]
-enum GameState {
-    StartScreen,
-    Playing,
-    GameOver,
-    Paused,
+// The game is laid out for this resolution and scaled to fit the window
+const VIRTUAL_WIDTH: f32 = 800.0;
+const VIRTUAL_HEIGHT: f32 = 600.0;
+// The world is rasterised at this lower resolution and upscaled without filtering
+const RENDER_WIDTH: u32 = 400;
+const RENDER_HEIGHT: u32 = 300;
+// Virtual units covered by one low-resolution pixel
+const PIXEL_SIZE: f32 = VIRTUAL_WIDTH / RENDER_WIDTH as f32;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ScaleMode {
+    Fit,     // Largest scale that fits, with letterbox bars
+    Integer, // Whole multiples of the render resolution, keeps pixels even
+}
+
+// Area of the window the virtual screen is drawn into
+struct Viewport {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    scale: f32,
+}
+
+// Screen point UI elements are positioned from
+#[derive(Clone, Copy)]
+enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Center,
+    Bottom,
+    BottomRight,
 }
 
 struct Camera {
     x: f32,
     y: f32,
+    // Horizontal point the dead zone is centred on
+    focus_x: f32,
+    // Vertical point the camera settles on, only updated on landing
+    focus_y: f32,
+    look_ahead: f32,
+}
+
+// Per-level tuning for how the camera follows the player
+#[derive(Clone, Copy)]
+struct CameraSettings {
+    dead_zone_width: f32,  // Player can move this far without scrolling
+    look_ahead: f32,       // Distance shown ahead of the facing direction
+    look_ahead_speed: f32, // How fast the look-ahead swings when turning
+    smoothing: f32,        // Lerp rate, higher values catch up faster
+}
+
+// Region of a room that overrides normal camera follow
+struct CameraZone {
+    area: Rect,
+    mode: CameraMode,
+}
+
+#[derive(Clone, Copy)]
+enum CameraMode {
+    Locked { x: f32, y: f32 }, // Holds the screen until every enemy inside is defeated
+    VerticalOnly,              // Centred horizontally on the zone, always follows vertically
+    AutoScroll { speed: f32 }, // Scrolls right on its own, pushing the player along
+}
+
+impl CameraSettings {
+    fn standard() -> Self {
+        CameraSettings {
+            dead_zone_width: 80.0,
+            look_ahead: 60.0,
+            look_ahead_speed: 150.0,
+            smoothing: 6.0,
+        }
+    }
 }
 
 impl Camera {
     fn new() -> Self {
-        Camera { x: 0.0, y: 0.0 }
+        Camera { x: 0.0, y: 0.0, focus_x: VIRTUAL_WIDTH / 2.0, focus_y: VIRTUAL_HEIGHT / 2.0, look_ahead: 0.0 }
     }
 
-    fn follow_player(&mut self, player: &Player) {
-        let screen_width = 800.0;
-        let screen_height = 600.0;
-        
-        self.x = player.x + player.width / 2.0 - screen_width / 2.0;
-        self.y = player.y + player.height / 2.0 - screen_height / 2.0;
+    fn update(&mut self, dt: f32, player: PlayerView, settings: &CameraSettings, bounds: Rect, zone: Option<&CameraZone>) {
+        let screen_width = VIRTUAL_WIDTH;
+        let screen_height = VIRTUAL_HEIGHT;
+        let player_x = player.rect.x + player.rect.w / 2.0;
+        let player_y = player.rect.y + player.rect.h / 2.0;
+
+        // Only scroll once the player leaves the dead zone
+        let half_zone = settings.dead_zone_width / 2.0;
+        if player_x > self.focus_x + half_zone {
+            self.focus_x = player_x - half_zone;
+        } else if player_x < self.focus_x - half_zone {
+            self.focus_x = player_x + half_zone;
+        }
+
+        let look_ahead_target = if player.facing_right { settings.look_ahead } else { -settings.look_ahead };
+        let max_step = settings.look_ahead_speed * dt;
+        self.look_ahead += (look_ahead_target - self.look_ahead).clamp(-max_step, max_step);
+
+        // Vertical follow waits for landing so jumps don't bob the screen,
+        // but still chases the player if they would leave the screen
+        let vertical_only = matches!(zone, Some(CameraZone { mode: CameraMode::VerticalOnly, .. }));
+        if player.on_ground || vertical_only {
+            self.focus_y = player_y;
+        } else {
+            let margin = screen_height / 2.0 - player.rect.h * 2.0;
+            self.focus_y = self.focus_y.clamp(player_y - margin, player_y + margin);
+        }
+
+        let mut target_x = self.focus_x + self.look_ahead - screen_width / 2.0;
+        let mut target_y = self.focus_y - screen_height / 2.0;
+        let blend = 1.0 - (-settings.smoothing * dt).exp();
+
+        if let Some(zone) = zone {
+            match zone.mode {
+                CameraMode::Locked { x, y } => {
+                    target_x = x;
+                    target_y = y;
+                }
+                CameraMode::VerticalOnly => {
+                    target_x = zone.area.x + (zone.area.w - screen_width) / 2.0;
+                }
+                CameraMode::AutoScroll { speed } => {
+                    // Scroll at a fixed pace until the end of the section,
+                    // keeping the follow state in sync for when it ends
+                    self.x = (self.x + speed * dt).min(zone.area.x + zone.area.w - screen_width);
+                    self.focus_x = self.x + screen_width / 2.0;
+                    self.look_ahead = 0.0;
+                    self.y += (target_y - self.y) * blend;
+                    self.clamp_to(bounds);
+                    return;
+                }
+            }
+        }
+
+        self.x += (target_x - self.x) * blend;
+        self.y += (target_y - self.y) * blend;
+
+        self.clamp_to(bounds);
+    }
+
+    fn snap_to(&mut self, player: PlayerView, bounds: Rect) {
+        // Jump straight to the player, used after warps and respawns
+        self.focus_x = player.rect.x + player.rect.w / 2.0;
+        self.focus_y = player.rect.y + player.rect.h / 2.0;
+        self.look_ahead = 0.0;
+        self.x = self.focus_x - VIRTUAL_WIDTH / 2.0;
+        self.y = self.focus_y - VIRTUAL_HEIGHT / 2.0;
+        self.clamp_to(bounds);
+    }
+
+    fn clamp_to(&mut self, bounds: Rect) {
+        // Centre on rooms smaller than the screen instead of clamping
+        if bounds.w <= VIRTUAL_WIDTH {
+            self.x = bounds.x + (bounds.w - VIRTUAL_WIDTH) / 2.0;
+        } else {
+            self.x = self.x.clamp(bounds.x, bounds.x + bounds.w - VIRTUAL_WIDTH);
+        }
+        if bounds.h <= VIRTUAL_HEIGHT {
+            self.y = bounds.y + (bounds.h - VIRTUAL_HEIGHT) / 2.0;
+        } else {
+            self.y = self.y.clamp(bounds.y, bounds.y + bounds.h - VIRTUAL_HEIGHT);
+        }
+    }
+
+    fn apply(&self, canvas: &Canvas) {
+        set_camera(&canvas.camera(self.x, self.y));
+    }
+}
+
+// Rounds a virtual coordinate to the nearest low-resolution pixel
+fn snap_to_pixel(value: f32) -> f32 {
+    (value / PIXEL_SIZE).round() * PIXEL_SIZE
+}
+
+// Offscreen low-resolution target the background and world are drawn into
+struct Canvas {
+    target: RenderTarget,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        let target = render_target(RENDER_WIDTH, RENDER_HEIGHT);
+        target.texture.set_filter(FilterMode::Nearest);
+        Canvas { target }
     }
 
-    fn apply(&self) {
-        set_camera(&Camera2D {
-            target: Vec2::new(self.x + 400.0, self.y + 300.0),
-            zoom: Vec2::new(1.0 / 400.0, 1.0 / 300.0),
+    // Camera showing the virtual screen with its top-left corner at (x, y),
+    // snapped so scrolling moves whole pixels instead of shimmering
+    fn camera(&self, x: f32, y: f32) -> Camera2D {
+        Camera2D {
+            target: vec2(snap_to_pixel(x) + VIRTUAL_WIDTH / 2.0, snap_to_pixel(y) + VIRTUAL_HEIGHT / 2.0),
+            zoom: vec2(2.0 / VIRTUAL_WIDTH, 2.0 / VIRTUAL_HEIGHT),
+            render_target: Some(self.target.clone()),
             ..Default::default()
-        });
+        }
+    }
+
+    // Upscales the finished frame into the window
+    fn present(&self, viewport: &Viewport) {
+        set_default_camera();
+        draw_texture_ex(
+            &self.target.texture,
+            viewport.x,
+            viewport.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(viewport.width, viewport.height)),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+impl Viewport {
+    fn current(mode: ScaleMode) -> Self {
+        // Measured in low-resolution pixels so integer mode never splits one
+        let fit = (screen_width() / RENDER_WIDTH as f32).min(screen_height() / RENDER_HEIGHT as f32);
+        let pixel_scale = match mode {
+            ScaleMode::Fit => fit,
+            // Fall back to fitting when the window is smaller than 1x
+            ScaleMode::Integer if fit >= 1.0 => fit.floor(),
+            ScaleMode::Integer => fit,
+        };
+        let scale = pixel_scale / PIXEL_SIZE;
+        let width = VIRTUAL_WIDTH * scale;
+        let height = VIRTUAL_HEIGHT * scale;
+        Viewport {
+            x: ((screen_width() - width) / 2.0).floor(),
+            y: ((screen_height() - height) / 2.0).floor(),
+            width,
+            height,
+            scale,
+        }
+    }
+
+    // Camera for drawing in virtual screen coordinates at window resolution
+    fn screen_camera(&self) -> Camera2D {
+        Camera2D {
+            target: vec2(VIRTUAL_WIDTH / 2.0, VIRTUAL_HEIGHT / 2.0),
+            zoom: vec2(2.0 / VIRTUAL_WIDTH, 2.0 / VIRTUAL_HEIGHT),
+            viewport: Some((self.x as i32, self.y as i32, self.width as i32, self.height as i32)),
+            ..Default::default()
+        }
+    }
+
+    // Window position of an anchor plus an offset in virtual pixels.
+    // Use with the default camera so UI is drawn at native resolution
+    fn anchor(&self, anchor: Anchor, offset_x: f32, offset_y: f32) -> Vec2 {
+        let (fraction_x, fraction_y) = match anchor {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        };
+        vec2(
+            self.x + self.width * fraction_x + offset_x * self.scale,
+            self.y + self.height * fraction_y + offset_y * self.scale,
+        )
+    }
+
+    // Text is aligned to the side of the screen its anchor is on and
+    // centred for anchors in the middle
+    fn text(&self, text: &str, anchor: Anchor, offset_x: f32, offset_y: f32, size: f32, color: Color) {
+        let position = self.anchor(anchor, offset_x, offset_y);
+        let font_size = size * self.scale;
+        let width = measure_text(text, None, font_size as u16, 1.0).width;
+        let x = match anchor {
+            Anchor::TopLeft => position.x,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => position.x - width / 2.0,
+            Anchor::TopRight | Anchor::BottomRight => position.x - width,
+        };
+        draw_text(text, x, position.y, font_size, color);
     }
 }
 
@@ -41,1000 +288,5739 @@ struct Platform {
     y: f32,
     width: f32,
     height: f32,
+    kind: PlatformKind,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PlatformKind {
+    Ground,
+    Pipe,
+    Brick, // Breaks when hit from below
 }
 
-struct Enemy {
+// Game objects are entities: a slot in each of a World's component columns,
+// filled in for the components it has
+type Entity = usize;
+
+// Slot the player takes in whichever room's world they're in
+const PLAYER: Entity = 0;
+
+#[derive(Clone, Copy, Default)]
+struct Transform {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Velocity {
     x: f32,
     y: f32,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Collider {
     width: f32,
     height: f32,
-    velocity_x: f32,
-    velocity_y: f32,
+}
+
+// Blocked by platforms on every side, where other entities only land on them
+#[derive(Clone, Copy, Default)]
+struct Body {
+    on_ground: bool,
+    bumped: Option<usize>, // Platform hit head-first during the last update
+}
+
+struct Sprite {
+    kind: SpriteKind,
+    animator: Option<Animator>, // Only for sprites with animation clips
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SpriteKind {
+    Player,
+    Enemy,
+    Coin,
+    PowerUp(PowerUpType),
+}
+
+// Collected when the player touches it
+#[derive(Clone, Copy)]
+enum Pickup {
+    Coin,
+    PowerUp(PowerUpType),
+}
+
+// Hurts the player on touch, unless landed on
+#[derive(Clone, Copy)]
+struct Hostile;
+
+// Walks back and forth, turning at the ends of whatever it stands on
+#[derive(Clone, Copy)]
+struct Patrol {
+    speed: f32,
     direction: f32,
-    alive: bool,
 }
 
-struct Coin {
+// Removed once its one-shot animation finishes, like a stomped enemy
+#[derive(Clone, Copy)]
+struct Corpse;
+
+// Every component an entity can have, for spawning it or moving it between worlds
+#[derive(Default)]
+struct Bundle {
+    transform: Transform,
+    collider: Collider,
+    velocity: Option<Velocity>,
+    body: Option<Body>,
+    sprite: Option<Sprite>,
+    pickup: Option<Pickup>,
+    hostile: Option<Hostile>,
+    patrol: Option<Patrol>,
+    corpse: Option<Corpse>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PowerUpType {
+    SpeedBoost,
+    JumpBoost,
+}
+
+// Entrance that moves the player to another room of the same level
+struct Warp {
     x: f32,
     y: f32,
     width: f32,
     height: f32,
-    collected: bool,
+    kind: WarpKind,
+    target_room: usize,
+    target_x: f32,
+    target_y: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum WarpKind {
+    Pipe, // Entered by pressing down while standing on top
+    Door, // Entered by pressing up while standing in front
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Theme {
+    Overworld,
+    Underground,
+    Castle,
+    Sky,
+    Ice,
+}
+
+// Colours a theme uses for terrain and enemies
+struct Palette {
+    surface: Color,       // Top layer of platforms (grass, snow, stone...)
+    surface_light: Color, // Highlight along the very top edge
+    body: Color,          // Platform fill below the surface
+    detail: Color,        // Speckles, bricks or cracks inside the fill
+    outline: Color,       // Dark edge around the platform
+    enemy_body: Color,
+    enemy_head: Color,
+    enemy_eyes: Color,
+}
+
+// One scrolling strip of scenery drawn behind the level
+struct ParallaxLayer {
+    kind: LayerKind,
+    scroll: f32,  // Fraction of the camera movement the layer follows
+    spacing: f32, // Distance between repeats of the layer's motif
+    y: f32,       // Screen height of the motif when the camera is at y = 0
+}
+
+#[derive(Clone, Copy)]
+enum LayerKind {
+    Hills,
+    Clouds,
+    Bushes,
+    RockPillars,
+    Stalactites,
+    CastleTowers,
+    Banners,
+    CloudBank,
+    SnowyHills,
 }
 
-struct PowerUp {
+// Textures are generated once per theme instead of drawn line by line
+struct ThemeArt {
+    gradients: Vec<Texture2D>, // Indexed by Theme
+    tilesets: Vec<Texture2D>,  // 9-slice platform tiles, indexed by Theme
+}
+
+// Named frames packed into one texture. Drawing everything from a single
+// texture lets macroquad batch the sprites into as few draw calls as possible
+struct SpriteAtlas {
+    texture: Texture2D,
+    frames: HashMap<String, SpriteFrame>,
+}
+
+struct SpriteFrame {
+    source: Rect,
+    offset: Vec2, // Where the frame sits relative to the entity position
+}
+
+// Sprites come from the atlas when it is present, otherwise every entity
+// falls back to its procedural rectangles
+struct Sprites {
+    atlas: Option<SpriteAtlas>,
+}
+
+// Where an entity is drawn. Procedural sprites place their rectangles
+// through this so they can be mirrored when facing left
+#[derive(Clone, Copy)]
+struct SpriteCanvas {
     x: f32,
     y: f32,
     width: f32,
-    height: f32,
-    collected: bool,
-    power_type: PowerUpType,
+    flip_x: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum AnimState {
+    Idle,
+    Walk,
+    Run,
+    Jump,
+    Fall,
+    Hurt,
+    Die,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PlayMode {
+    Loop,
+    Once, // Holds the last frame when done
+}
+
+struct AnimFrame {
+    sprite: &'static str,
+    duration: f32,
+}
+
+struct AnimClip {
+    name: &'static str,
+    frames: &'static [AnimFrame],
+    mode: PlayMode,
+}
+
+// Plays the clip matching an entity's current state. Clips are looked up by
+// name and the first clip in the set is used when a state has none
+struct Animator {
+    clips: &'static [AnimClip],
+    state: AnimState,
+    clip: usize,
+    frame: usize,
+    timer: f32,
+    flip_x: bool,
+}
+
+const PLAYER_CLIPS: &[AnimClip] = &[
+    AnimClip {
+        name: "idle",
+        frames: &[AnimFrame { sprite: "player_idle", duration: 1.0 }],
+        mode: PlayMode::Loop,
+    },
+    AnimClip {
+        name: "walk",
+        frames: &[
+            AnimFrame { sprite: "player_walk_0", duration: 0.125 },
+            AnimFrame { sprite: "player_walk_1", duration: 0.125 },
+        ],
+        mode: PlayMode::Loop,
+    },
+    AnimClip {
+        name: "run",
+        frames: &[
+            AnimFrame { sprite: "player_walk_0", duration: 0.07 },
+            AnimFrame { sprite: "player_run", duration: 0.05 },
+            AnimFrame { sprite: "player_walk_1", duration: 0.07 },
+            AnimFrame { sprite: "player_run", duration: 0.05 },
+        ],
+        mode: PlayMode::Loop,
+    },
+    AnimClip {
+        name: "jump",
+        frames: &[AnimFrame { sprite: "player_jump", duration: 1.0 }],
+        mode: PlayMode::Once,
+    },
+    AnimClip {
+        name: "fall",
+        frames: &[AnimFrame { sprite: "player_fall", duration: 1.0 }],
+        mode: PlayMode::Once,
+    },
+    AnimClip {
+        name: "hurt",
+        frames: &[
+            AnimFrame { sprite: "player_hurt", duration: 0.08 },
+            AnimFrame { sprite: "player_idle", duration: 0.08 },
+        ],
+        mode: PlayMode::Loop,
+    },
+    AnimClip {
+        name: "die",
+        frames: &[AnimFrame { sprite: "player_die", duration: 1.0 }],
+        mode: PlayMode::Once,
+    },
+];
+
+const ENEMY_CLIPS: &[AnimClip] = &[
+    AnimClip {
+        name: "walk",
+        frames: &[
+            AnimFrame { sprite: "enemy_walk_0", duration: 0.2 },
+            AnimFrame { sprite: "enemy_walk_1", duration: 0.2 },
+        ],
+        mode: PlayMode::Loop,
+    },
+    AnimClip {
+        name: "die",
+        frames: &[AnimFrame { sprite: "enemy_squashed", duration: 0.5 }],
+        mode: PlayMode::Once,
+    },
+];
+
+// Describes how an effect spawns and moves its particles
+struct EmitterDef {
+    burst: usize,           // Particles spawned at once by one-off effects
+    rate: f32,              // Particles per second for continuous effects
+    lifetime: (f32, f32),
+    speed: (f32, f32),
+    angle: (f32, f32),      // Launch direction in degrees, 0 is right and -90 is up
+    gravity: f32,
+    size: (f32, f32),
+    colors: &'static [Color], // Ramp the colour moves through over its life
+}
+
+#[derive(Clone, Copy)]
+struct Particle {
+    x: f32,
+    y: f32,
+    velocity_x: f32,
+    velocity_y: f32,
+    age: f32,
+    lifetime: f32,
+    size: f32,
+    def: &'static EmitterDef,
+}
+
+// Fixed-size pool, spawning reuses dead slots so effects never allocate
+struct Particles {
+    pool: Vec<Particle>,
+    next: usize,
+    trail_timer: f32,
+}
+
+const DUST: EmitterDef = EmitterDef {
+    burst: 6,
+    rate: 0.0,
+    lifetime: (0.25, 0.45),
+    speed: (30.0, 70.0),
+    angle: (-170.0, -10.0),
+    gravity: 60.0,
+    size: (3.0, 5.0),
+    colors: &[Color::new(0.9, 0.85, 0.75, 0.8), Color::new(0.7, 0.65, 0.55, 0.0)],
+};
+
+const POOF: EmitterDef = EmitterDef {
+    burst: 10,
+    rate: 0.0,
+    lifetime: (0.3, 0.5),
+    speed: (60.0, 120.0),
+    angle: (-180.0, 0.0),
+    gravity: -40.0,
+    size: (4.0, 7.0),
+    colors: &[WHITE, Color::new(0.8, 0.8, 0.8, 0.6), Color::new(0.6, 0.6, 0.6, 0.0)],
+};
+
+const SPARKLE: EmitterDef = EmitterDef {
+    burst: 8,
+    rate: 0.0,
+    lifetime: (0.3, 0.6),
+    speed: (40.0, 110.0),
+    angle: (-180.0, 180.0),
+    gravity: 0.0,
+    size: (2.0, 3.0),
+    colors: &[WHITE, YELLOW, Color::new(1.0, 0.8, 0.0, 0.0)],
+};
+
+const DEBRIS: EmitterDef = EmitterDef {
+    burst: 12,
+    rate: 0.0,
+    lifetime: (0.6, 1.0),
+    speed: (120.0, 260.0),
+    angle: (-150.0, -30.0),
+    gravity: 900.0,
+    size: (4.0, 7.0),
+    colors: &[Color::new(0.75, 0.4, 0.15, 1.0), Color::new(0.3, 0.15, 0.05, 1.0)],
+};
+
+const SPEED_TRAIL: EmitterDef = EmitterDef {
+    burst: 0,
+    rate: 40.0,
+    lifetime: (0.2, 0.35),
+    speed: (5.0, 20.0),
+    angle: (-120.0, -60.0),
+    gravity: 0.0,
+    size: (3.0, 5.0),
+    colors: &[ORANGE, Color::new(1.0, 0.3, 0.0, 0.0)],
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum PopupText {
+    Points(i32),
+    OneUp,
 }
 
+// Score text that floats up from where points were earned
 #[derive(Clone)]
-enum PowerUpType {
-    SpeedBoost,
-    JumpBoost,
+struct Popup {
+    x: f32,
+    y: f32,
+    age: f32,
+    label: String, // Formatted on spawn, into the slot's own buffer
+    color: Color,
+}
+
+// Reuses a fixed set of slots like the particle pool
+struct Popups {
+    pool: Vec<Popup>,
+    next: usize,
+}
+
+// Broadphase cell size: a few tiles, around the size of a platform or two
+const GRID_CELL_SIZE: f32 = 128.0;
+
+// Points for consecutive stomps without landing, a 1-up after the last
+const STOMP_CHAIN: [i32; 5] = [100, 200, 400, 800, 1000];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Music {
+    Overworld,
+    Underground,
+    Castle,
+    Sky,
+    Ice,
+    Bonus,
+}
+
+#[derive(Clone, Copy)]
+enum Sound {
+    Jump,
+    Coin,
+    EnemyDefeat,
+    PowerUp,
+    Hit,
+    LevelComplete,
+    CoinOneUp,
+    OneUp,
+    Brick,
+    Warp,
+}
+
+// Console stand-in for real audio, at the volumes set in the options
+struct Audio {
+    music_volume: u8, // 0-10, silent at 0
+    sound_volume: u8,
 }
 
 impl Platform {
     fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
-        Platform { x, y, width, height }
+        Platform { x, y, width, height, kind: PlatformKind::Ground }
     }
 
-    fn draw(&self) {
-        // Draw platform with grass texture
-        draw_rectangle(self.x, self.y, self.width, self.height, DARKGREEN);
-        // Add grass tufts on top
-        let grass_spacing = 8.0;
-        let mut x = self.x;
-        while x < self.x + self.width - 4.0 {
-            draw_rectangle(x, self.y - 2.0, 4.0, 2.0, GREEN);
-            x += grass_spacing;
-        }
-        // Add some dirt pattern
-        let dirt_spacing = 16.0;
-        let mut x = self.x + 4.0;
-        while x < self.x + self.width - 8.0 {
-            draw_rectangle(x, self.y + 4.0, 8.0, 4.0, BROWN);
-            x += dirt_spacing;
-        }
+    fn pipe(x: f32, y: f32, height: f32) -> Self {
+        Platform { x, y, width: 48.0, height, kind: PlatformKind::Pipe }
     }
-}
 
-impl Enemy {
-    fn new(x: f32, y: f32) -> Self {
-        Enemy {
-            x,
-            y,
-            width: 24.0,
-            height: 24.0,
-            velocity_x: 50.0,
-            velocity_y: 0.0,
-            direction: 1.0,
-            alive: true,
-        }
+    fn brick(x: f32, y: f32) -> Self {
+        Platform { x, y, width: 32.0, height: 32.0, kind: PlatformKind::Brick }
     }
 
-    fn update(&mut self, dt: f32, platforms: &[Platform]) {
-        if !self.alive {
-            return;
-        }
+    fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
 
-        let gravity = 800.0;
-        self.velocity_y += gravity * dt;
-
-        // Move horizontally
-        self.x += self.velocity_x * self.direction * dt;
-
-        // Check for platform edges or walls
-        let mut _on_platform = false;
-        for platform in platforms {
-            // Check if enemy is on this platform
-            if self.x + self.width > platform.x &&
-               self.x < platform.x + platform.width &&
-               self.y + self.height >= platform.y &&
-               self.y + self.height <= platform.y + 10.0 {
-                _on_platform = true;
-                
-                // Check if enemy is near platform edge
-                if self.direction > 0.0 && self.x + self.width >= platform.x + platform.width {
-                    self.direction = -1.0;
-                } else if self.direction < 0.0 && self.x <= platform.x {
-                    self.direction = 1.0;
-                }
-            }
+    fn draw(&self, tileset: &Texture2D) {
+        match self.kind {
+            PlatformKind::Pipe => return self.draw_pipe(),
+            PlatformKind::Brick => return self.draw_brick(),
+            PlatformKind::Ground => {}
         }
+        draw_nine_slice(tileset, self.rect(), TILE_BORDER);
+    }
 
-        // Apply gravity and handle vertical collisions
-        self.y += self.velocity_y * dt;
-
-        for platform in platforms {
-            if self.x < platform.x + platform.width &&
-               self.x + self.width > platform.x &&
-               self.y < platform.y + platform.height &&
-               self.y + self.height > platform.y {
-                
-                if self.velocity_y > 0.0 {
-                    self.y = platform.y - self.height;
-                    self.velocity_y = 0.0;
+    fn draw_brick(&self) {
+        let mortar = Color::new(0.3, 0.15, 0.05, 1.0);
+        draw_rectangle(self.x, self.y, self.width, self.height, mortar);
+        // Two rows of offset bricks
+        for row in 0..2 {
+            let y = self.y + row as f32 * 16.0 + 1.0;
+            let shift = if row == 0 { 0.0 } else { -8.0 };
+            for column in 0..3 {
+                let left = (self.x + shift + column as f32 * 16.0 + 1.0).max(self.x + 1.0);
+                let right = (self.x + shift + column as f32 * 16.0 + 15.0).min(self.x + self.width - 1.0);
+                if right > left {
+                    draw_rectangle(left, y, right - left, 14.0, Color::new(0.75, 0.4, 0.15, 1.0));
+                    draw_rectangle(left, y, right - left, 2.0, Color::new(0.9, 0.55, 0.3, 1.0));
                 }
             }
         }
     }
 
-    fn check_collision(&self, player: &Player) -> bool {
-        self.alive &&
-        self.x < player.x + player.width &&
-        self.x + self.width > player.x &&
-        self.y < player.y + player.height &&
-        self.y + self.height > player.y
+    fn draw_pipe(&self) {
+        // Pipe body
+        draw_rectangle(self.x + 4.0, self.y, self.width - 8.0, self.height, DARKGREEN);
+        draw_rectangle(self.x + 10.0, self.y, 6.0, self.height, LIME);
+        // Pipe rim
+        draw_rectangle(self.x, self.y, self.width, 16.0, GREEN);
+        draw_rectangle(self.x + 6.0, self.y + 2.0, 6.0, 12.0, LIME);
+        draw_rectangle(self.x, self.y + 14.0, self.width, 2.0, DARKGREEN);
     }
+}
 
-    fn draw(&self) {
-        if self.alive {
-            // Draw simple enemy sprite
-            // Body
-            draw_rectangle(self.x + 2.0, self.y + 8.0, 20.0, 16.0, PURPLE);
-            // Head
-            draw_rectangle(self.x + 4.0, self.y, 16.0, 12.0, DARKPURPLE);
-            // Eyes
-            draw_rectangle(self.x + 7.0, self.y + 3.0, 3.0, 3.0, RED);
-            draw_rectangle(self.x + 14.0, self.y + 3.0, 3.0, 3.0, RED);
-            // Feet
-            draw_rectangle(self.x, self.y + 20.0, 6.0, 4.0, BLACK);
-            draw_rectangle(self.x + 18.0, self.y + 20.0, 6.0, 4.0, BLACK);
+impl Warp {
+    fn pipe(x: f32, y: f32, target_room: usize, target_x: f32, target_y: f32) -> Self {
+        // Trigger sits on the pipe's top edge
+        Warp {
+            x,
+            y,
+            width: 48.0,
+            height: 2.0,
+            kind: WarpKind::Pipe,
+            target_room,
+            target_x,
+            target_y,
         }
     }
-}
 
-impl Coin {
-    fn new(x: f32, y: f32) -> Self {
-        Coin {
+    fn door(x: f32, y: f32, target_room: usize, target_x: f32, target_y: f32) -> Self {
+        Warp {
             x,
             y,
-            width: 16.0,
-            height: 16.0,
-            collected: false,
+            width: 32.0,
+            height: 48.0,
+            kind: WarpKind::Door,
+            target_room,
+            target_x,
+            target_y,
         }
     }
 
-    fn check_collision(&self, player: &Player) -> bool {
-        !self.collected &&
-        self.x < player.x + player.width &&
-        self.x + self.width > player.x &&
-        self.y < player.y + player.height &&
-        self.y + self.height > player.y
+    fn can_enter(&self, player: PlayerView, input: &Input) -> bool {
+        let rect = player.rect;
+        let center_x = rect.x + rect.w / 2.0;
+        if center_x < self.x + 8.0 || center_x > self.x + self.width - 8.0 {
+            return false;
+        }
+        match self.kind {
+            WarpKind::Pipe => {
+                player.on_ground &&
+                (rect.y + rect.h - self.y).abs() < 1.0 &&
+                input.pressed(Action::Down)
+            }
+            WarpKind::Door => {
+                player.on_ground &&
+                rect.y < self.y + self.height &&
+                rect.y + rect.h > self.y &&
+                input.pressed(Action::Up)
+            }
+        }
     }
 
     fn draw(&self) {
-        if !self.collected {
-            // Draw coin with sparkle effect
-            draw_rectangle(self.x + 2.0, self.y + 2.0, 12.0, 12.0, GOLD);
-            draw_rectangle(self.x + 4.0, self.y + 4.0, 8.0, 8.0, YELLOW);
-            // Add sparkle
-            draw_rectangle(self.x + 1.0, self.y + 7.0, 2.0, 2.0, WHITE);
-            draw_rectangle(self.x + 13.0, self.y + 7.0, 2.0, 2.0, WHITE);
+        // Pipes are drawn by their platform, doors need their own sprite
+        if self.kind == WarpKind::Door {
+            // Frame
+            draw_rectangle(self.x - 2.0, self.y - 2.0, self.width + 4.0, self.height + 2.0, DARKBROWN);
+            // Door panels
+            draw_rectangle(self.x, self.y, self.width, self.height, BROWN);
+            draw_rectangle(self.x + 4.0, self.y + 4.0, 10.0, 18.0, DARKBROWN);
+            draw_rectangle(self.x + 18.0, self.y + 4.0, 10.0, 18.0, DARKBROWN);
+            // Knob
+            draw_rectangle(self.x + 24.0, self.y + 26.0, 4.0, 4.0, GOLD);
         }
     }
 }
 
-impl PowerUp {
-    fn new(x: f32, y: f32, power_type: PowerUpType) -> Self {
-        PowerUp {
-            x,
-            y,
-            width: 20.0,
-            height: 20.0,
-            collected: false,
-            power_type,
+impl Theme {
+    const ALL: [Theme; 5] = [Theme::Overworld, Theme::Underground, Theme::Castle, Theme::Sky, Theme::Ice];
+
+    fn palette(&self) -> Palette {
+        match self {
+            Theme::Overworld => Palette {
+                surface: GREEN,
+                surface_light: LIME,
+                body: DARKGREEN,
+                detail: BROWN,
+                outline: Color::new(0.0, 0.25, 0.1, 1.0),
+                enemy_body: PURPLE,
+                enemy_head: DARKPURPLE,
+                enemy_eyes: RED,
+            },
+            Theme::Underground => Palette {
+                surface: Color::new(0.35, 0.5, 0.65, 1.0),
+                surface_light: Color::new(0.5, 0.65, 0.8, 1.0),
+                body: Color::new(0.2, 0.25, 0.4, 1.0),
+                detail: Color::new(0.12, 0.15, 0.28, 1.0),
+                outline: Color::new(0.08, 0.1, 0.18, 1.0),
+                enemy_body: Color::new(0.2, 0.4, 0.7, 1.0),
+                enemy_head: Color::new(0.1, 0.25, 0.5, 1.0),
+                enemy_eyes: WHITE,
+            },
+            Theme::Castle => Palette {
+                surface: LIGHTGRAY,
+                surface_light: WHITE,
+                body: GRAY,
+                detail: DARKGRAY,
+                outline: Color::new(0.2, 0.2, 0.22, 1.0),
+                enemy_body: MAROON,
+                enemy_head: Color::new(0.4, 0.0, 0.05, 1.0),
+                enemy_eyes: YELLOW,
+            },
+            Theme::Sky => Palette {
+                surface: WHITE,
+                surface_light: WHITE,
+                body: Color::new(0.85, 0.9, 1.0, 1.0),
+                detail: Color::new(0.7, 0.8, 0.95, 1.0),
+                outline: Color::new(0.55, 0.65, 0.85, 1.0),
+                enemy_body: PINK,
+                enemy_head: MAGENTA,
+                enemy_eyes: BLACK,
+            },
+            Theme::Ice => Palette {
+                surface: WHITE,
+                surface_light: Color::new(0.9, 0.97, 1.0, 1.0),
+                body: Color::new(0.6, 0.85, 0.95, 1.0),
+                detail: Color::new(0.4, 0.7, 0.9, 1.0),
+                outline: Color::new(0.2, 0.45, 0.65, 1.0),
+                enemy_body: Color::new(0.3, 0.6, 0.8, 1.0),
+                enemy_head: Color::new(0.15, 0.4, 0.6, 1.0),
+                enemy_eyes: DARKBLUE,
+            },
         }
     }
 
-    fn check_collision(&self, player: &Player) -> bool {
-        !self.collected &&
-        self.x < player.x + player.width &&
-        self.x + self.width > player.x &&
-        self.y < player.y + player.height &&
-        self.y + self.height > player.y
+    fn gradient(&self, ratio: f32) -> Color {
+        match self {
+            // Light blue to darker blue
+            Theme::Overworld => Color::new(0.5 + ratio * 0.2, 0.8 + ratio * 0.1, 1.0, 1.0),
+            // Dark cave fading to black
+            Theme::Underground => Color::new(0.15 - ratio * 0.1, 0.1 - ratio * 0.05, 0.2 - ratio * 0.1, 1.0),
+            // Dusk red behind the castle walls
+            Theme::Castle => Color::new(0.2 + ratio * 0.25, 0.08 + ratio * 0.05, 0.15, 1.0),
+            // Deep blue high above, brighter near the cloud bank
+            Theme::Sky => Color::new(0.3 + ratio * 0.4, 0.55 + ratio * 0.35, 0.95, 1.0),
+            // Pale frosty sky
+            Theme::Ice => Color::new(0.75 + ratio * 0.15, 0.88 + ratio * 0.08, 1.0, 1.0),
+        }
     }
 
-    fn draw(&self) {
-        if !self.collected {
-            let (primary_color, secondary_color) = match self.power_type {
-                PowerUpType::SpeedBoost => (ORANGE, RED),
-                PowerUpType::JumpBoost => (BLUE, SKYBLUE),
-            };
-            // Draw power-up with glow effect
-            draw_rectangle(self.x - 2.0, self.y - 2.0, self.width + 4.0, self.height + 4.0, secondary_color);
-            draw_rectangle(self.x, self.y, self.width, self.height, primary_color);
-            draw_rectangle(self.x + 4.0, self.y + 4.0, 12.0, 12.0, WHITE);
-            
-            // Add symbol
-            match self.power_type {
-                PowerUpType::SpeedBoost => {
-                    // Lightning bolt
-                    draw_rectangle(self.x + 8.0, self.y + 6.0, 2.0, 8.0, WHITE);
-                    draw_rectangle(self.x + 6.0, self.y + 8.0, 6.0, 2.0, WHITE);
-                }
-                PowerUpType::JumpBoost => {
-                    // Up arrow
-                    draw_rectangle(self.x + 9.0, self.y + 6.0, 2.0, 6.0, WHITE);
-                    draw_rectangle(self.x + 7.0, self.y + 8.0, 6.0, 2.0, WHITE);
-                }
-            }
+    fn layers(&self) -> &'static [ParallaxLayer] {
+        match self {
+            Theme::Overworld => &[
+                ParallaxLayer { kind: LayerKind::Hills, scroll: 0.2, spacing: 420.0, y: 470.0 },
+                ParallaxLayer { kind: LayerKind::Clouds, scroll: 0.35, spacing: 330.0, y: 110.0 },
+                ParallaxLayer { kind: LayerKind::Bushes, scroll: 0.7, spacing: 260.0, y: 560.0 },
+            ],
+            Theme::Underground => &[
+                ParallaxLayer { kind: LayerKind::RockPillars, scroll: 0.25, spacing: 300.0, y: 600.0 },
+                ParallaxLayer { kind: LayerKind::Stalactites, scroll: 0.5, spacing: 180.0, y: 0.0 },
+            ],
+            Theme::Castle => &[
+                ParallaxLayer { kind: LayerKind::CastleTowers, scroll: 0.25, spacing: 360.0, y: 520.0 },
+                ParallaxLayer { kind: LayerKind::Banners, scroll: 0.6, spacing: 240.0, y: 0.0 },
+            ],
+            Theme::Sky => &[
+                ParallaxLayer { kind: LayerKind::Clouds, scroll: 0.2, spacing: 260.0, y: 160.0 },
+                ParallaxLayer { kind: LayerKind::CloudBank, scroll: 0.5, spacing: 200.0, y: 540.0 },
+            ],
+            Theme::Ice => &[
+                ParallaxLayer { kind: LayerKind::SnowyHills, scroll: 0.2, spacing: 420.0, y: 470.0 },
+                ParallaxLayer { kind: LayerKind::Clouds, scroll: 0.35, spacing: 380.0, y: 100.0 },
+            ],
         }
     }
 }
 
-struct Player {
-    x: f32,
-    y: f32,
-    width: f32,
-    height: f32,
-    velocity_x: f32,
-    velocity_y: f32,
-    on_ground: bool,
-    score: i32,
-    speed_boost: f32,
-    jump_boost: f32,
-    lives: i32,
-    animation_timer: f32,
-    facing_right: bool,
-}
+// Width of the edge and corner pieces in a 9-slice tileset
+const TILE_BORDER: f32 = 8.0;
 
-struct Level {
-    platforms: Vec<Platform>,
-    enemies: Vec<Enemy>,
-    coins: Vec<Coin>,
-    powerups: Vec<PowerUp>,
-    goal_x: f32,
-    goal_y: f32,
-}
+impl ThemeArt {
+    fn new() -> Self {
+        let height = 600;
+        let gradients = Theme::ALL.iter().map(|theme| {
+            let mut image = Image::gen_image_color(1, height, WHITE);
+            for y in 0..height {
+                image.set_pixel(0, y as u32, theme.gradient(y as f32 / height as f32));
+            }
+            Texture2D::from_image(&image)
+        }).collect();
 
-struct Game {
-    state: GameState,
-    player: Player,
-    camera: Camera,
-    levels: Vec<Level>,
-    current_level: usize,
-    level_completed: bool,
-    // Simple audio using frequency synthesis (no external files needed)
-}
+        let tilesets = Theme::ALL.iter().map(|theme| {
+            let texture = Texture2D::from_image(&build_tileset(&theme.palette()));
+            texture.set_filter(FilterMode::Nearest);
+            texture
+        }).collect();
 
-impl Player {
-    fn new() -> Self {
-        Player {
-            x: 100.0,
-            y: 480.0,
-            width: 32.0,
-            height: 32.0,
-            velocity_x: 0.0,
-            velocity_y: 0.0,
-            on_ground: false,
-            score: 0,
-            speed_boost: 1.0,
-            jump_boost: 1.0,
-            lives: 3,
-            animation_timer: 0.0,
-            facing_right: true,
-        }
+        ThemeArt { gradients, tilesets }
     }
 
-    fn reset_position(&mut self) {
-        self.x = 100.0;
-        self.y = 480.0;
-        self.velocity_x = 0.0;
-        self.velocity_y = 0.0;
-        self.speed_boost = 1.0;
-        self.jump_boost = 1.0;
-        self.animation_timer = 0.0;
-        self.facing_right = true;
+    fn tileset(&self, theme: Theme) -> &Texture2D {
+        &self.tilesets[theme as usize]
     }
 
-    fn update(&mut self, dt: f32, platforms: &[Platform]) {
-        let gravity = 800.0;
-        
-        // Update animation timer
-        self.animation_timer += dt;
-        
-        self.velocity_y += gravity * dt;
-        
-        // Move horizontally first
-        self.x += self.velocity_x * dt;
-        
-        // Check horizontal collisions
-        for platform in platforms {
-            if self.x < platform.x + platform.width &&
-               self.x + self.width > platform.x &&
-               self.y < platform.y + platform.height &&
-               self.y + self.height > platform.y {
-                
-                // Horizontal collision - push player out
-                if self.velocity_x > 0.0 {
-                    // Moving right, hit left side of platform
-                    self.x = platform.x - self.width;
-                } else if self.velocity_x < 0.0 {
-                    // Moving left, hit right side of platform
-                    self.x = platform.x + platform.width;
-                }
-                self.velocity_x = 0.0;
-            }
-        }
-        
-        // Move vertically
-        self.y += self.velocity_y * dt;
-        self.on_ground = false;
-        
-        // Check vertical collisions
-        for platform in platforms {
-            if self.x < platform.x + platform.width &&
-               self.x + self.width > platform.x &&
-               self.y < platform.y + platform.height &&
-               self.y + self.height > platform.y {
-                
-                if self.velocity_y > 0.0 {
-                    // Falling down, hit top of platform
-                    self.y = platform.y - self.height;
-                    self.velocity_y = 0.0;
-                    self.on_ground = true;
-                } else if self.velocity_y < 0.0 {
-                    // Moving up, hit bottom of platform
-                    self.y = platform.y + platform.height;
-                    self.velocity_y = 0.0;
-                }
+    // Draws in screen space, call before applying the world camera
+    fn draw_background(&self, theme: Theme, camera_x: f32, camera_y: f32) {
+        draw_texture_ex(
+            &self.gradients[theme as usize],
+            0.0,
+            0.0,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(VIRTUAL_WIDTH, VIRTUAL_HEIGHT)),
+                ..Default::default()
+            },
+        );
+
+        for layer in theme.layers() {
+            let offset_x = snap_to_pixel(camera_x * layer.scroll);
+            let y = layer.y - snap_to_pixel(camera_y * layer.scroll);
+            // Repeat the motif over every slot visible on screen
+            let first = (offset_x / layer.spacing).floor() as i32 - 1;
+            let last = ((offset_x + VIRTUAL_WIDTH) / layer.spacing).ceil() as i32;
+            for slot in first..=last {
+                let x = slot as f32 * layer.spacing - offset_x;
+                draw_layer_motif(layer.kind, x, y, slot_variation(slot));
             }
         }
-        
-        self.velocity_x *= 0.8;
     }
+}
 
-    fn handle_input(&mut self) -> bool {
-        let speed = 200.0 * self.speed_boost;
-        let jump_force = -500.0 * self.jump_boost;
-        let mut jumped = false;
-        
-        if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {
-            self.velocity_x = -speed;
-            self.facing_right = false;
-        }
-        if is_key_down(KeyCode::D) || is_key_down(KeyCode::Right) {
-            self.velocity_x = speed;
-            self.facing_right = true;
-        }
-        if (is_key_pressed(KeyCode::W) || is_key_pressed(KeyCode::Up) || is_key_pressed(KeyCode::Space)) && self.on_ground {
-            self.velocity_y = jump_force;
-            jumped = true;
+// Builds a 24x24 tileset: 8px corners and edges around an 8px centre that
+// repeats, so platforms of any size keep crisp borders
+fn build_tileset(palette: &Palette) -> Image {
+    let size = 24;
+    let mut image = Image::gen_image_color(size, size, palette.body);
+    for y in 0..size {
+        for x in 0..size {
+            let color = if x < 2 || x >= size - 2 || y >= size - 2 {
+                palette.outline
+            } else if y < 2 {
+                palette.surface_light
+            } else if y < 6 {
+                palette.surface
+            } else if y < 8 {
+                // Surface drips into the fill every few pixels
+                if x % 4 < 2 { palette.surface } else { palette.body }
+            } else if (x % 8 == 3 && y % 8 == 2) || (x % 8 == 6 && y % 8 == 5) || (x % 8 == 4 && y % 8 == 6) {
+                palette.detail
+            } else {
+                palette.body
+            };
+            image.set_pixel(x as u32, y as u32, color);
         }
-        jumped
     }
+    image
+}
 
-    fn draw(&self) {
-        // Simple sprite-like rendering with animation
-        let is_moving = self.velocity_x.abs() > 10.0;
-        let is_jumping = !self.on_ground;
-        
-        if is_jumping {
-            // Jumping sprite - single frame
-            self.draw_jumping_sprite();
-        } else if is_moving {
-            // Walking animation - alternate between two frames
-            let frame = ((self.animation_timer * 8.0) as i32) % 2;
-            self.draw_walking_sprite(frame);
-        } else {
-            // Idle sprite
-            self.draw_idle_sprite();
+fn draw_nine_slice(texture: &Texture2D, dest: Rect, border: f32) {
+    // Shrink the border on platforms thinner than two edges
+    let border_x = border.min(dest.w / 2.0);
+    let border_y = border.min(dest.h / 2.0);
+    let source_size = texture.width();
+    let inner = source_size - border * 2.0;
+
+    let columns = [
+        (0.0, border_x, dest.x, border_x),
+        (border, inner, dest.x + border_x, dest.w - border_x * 2.0),
+        (source_size - border_x, border_x, dest.x + dest.w - border_x, border_x),
+    ];
+    let rows = [
+        (0.0, border_y, dest.y, border_y),
+        (border, inner, dest.y + border_y, dest.h - border_y * 2.0),
+        (source_size - border_y, border_y, dest.y + dest.h - border_y, border_y),
+    ];
+
+    for &(source_y, source_h, dest_y, dest_h) in &rows {
+        for &(source_x, source_w, dest_x, dest_w) in &columns {
+            draw_tiled(texture, Rect::new(source_x, source_y, source_w, source_h), Rect::new(dest_x, dest_y, dest_w, dest_h));
         }
     }
-    
-    fn draw_idle_sprite(&self) {
-        // Main body
-        draw_rectangle(self.x + 8.0, self.y + 4.0, 16.0, 24.0, RED);
-        // Head
-        draw_rectangle(self.x + 10.0, self.y, 12.0, 8.0, PINK);
-        // Eyes
-        draw_rectangle(self.x + 12.0, self.y + 2.0, 2.0, 2.0, BLACK);
-        draw_rectangle(self.x + 18.0, self.y + 2.0, 2.0, 2.0, BLACK);
-        // Feet
-        draw_rectangle(self.x + 6.0, self.y + 28.0, 6.0, 4.0, BROWN);
-        draw_rectangle(self.x + 20.0, self.y + 28.0, 6.0, 4.0, BROWN);
+}
+
+// Repeats a source region over the destination, cropping the last tiles
+fn draw_tiled(texture: &Texture2D, source: Rect, dest: Rect) {
+    if dest.w <= 0.0 || dest.h <= 0.0 {
+        return;
     }
-    
-    fn draw_walking_sprite(&self, frame: i32) {
-        // Main body
-        draw_rectangle(self.x + 8.0, self.y + 4.0, 16.0, 24.0, RED);
-        // Head
-        draw_rectangle(self.x + 10.0, self.y, 12.0, 8.0, PINK);
-        // Eyes
-        draw_rectangle(self.x + 12.0, self.y + 2.0, 2.0, 2.0, BLACK);
-        draw_rectangle(self.x + 18.0, self.y + 2.0, 2.0, 2.0, BLACK);
-        
-        // Animated feet
-        if frame == 0 {
-            draw_rectangle(self.x + 6.0, self.y + 28.0, 6.0, 4.0, BROWN);
-            draw_rectangle(self.x + 22.0, self.y + 30.0, 6.0, 2.0, BROWN);
-        } else {
-            draw_rectangle(self.x + 4.0, self.y + 30.0, 6.0, 2.0, BROWN);
-            draw_rectangle(self.x + 20.0, self.y + 28.0, 6.0, 4.0, BROWN);
+    let mut y = 0.0;
+    while y < dest.h {
+        let height = source.h.min(dest.h - y);
+        let mut x = 0.0;
+        while x < dest.w {
+            let width = source.w.min(dest.w - x);
+            draw_texture_ex(
+                texture,
+                dest.x + x,
+                dest.y + y,
+                WHITE,
+                DrawTextureParams {
+                    source: Some(Rect::new(source.x, source.y, width, height)),
+                    dest_size: Some(vec2(width, height)),
+                    ..Default::default()
+                },
+            );
+            x += width;
         }
-    }
-    
-    fn draw_jumping_sprite(&self) {
-        // Main body
-        draw_rectangle(self.x + 8.0, self.y + 4.0, 16.0, 24.0, RED);
-        // Head
-        draw_rectangle(self.x + 10.0, self.y, 12.0, 8.0, PINK);
-        // Eyes
-        draw_rectangle(self.x + 12.0, self.y + 2.0, 2.0, 2.0, BLACK);
-        draw_rectangle(self.x + 18.0, self.y + 2.0, 2.0, 2.0, BLACK);
-        // Feet together
-        draw_rectangle(self.x + 12.0, self.y + 28.0, 8.0, 4.0, BROWN);
+        y += height;
     }
 }
 
-// Simple audio synthesis functions
-fn play_jump_sound() {
-    // Create a simple jump sound using frequency sweep
-    let _frequency = 440.0; // A4 note
-    let _duration = 0.1;
-    // Note: In a real implementation, you'd use macroquad's audio system
-    // For now, we'll just use println to indicate sound events
-    println!("🔊 Jump sound!");
-}
+impl Sprites {
+    fn procedural() -> Self {
+        Sprites { atlas: None }
+    }
 
-fn play_coin_sound() {
-    println!("🔊 Coin collected!");
-}
+    // Loads `<path>.png` and its `<path>.atlas` frame list
+    async fn load(path: &str) -> Self {
+        let texture = match load_texture(&format!("{}.png", path)).await {
+            Ok(texture) => texture,
+            Err(_) => {
+                println!("No sprite atlas at {}.png, using procedural sprites", path);
+                return Sprites::procedural();
+            }
+        };
+        let metadata = match load_string(&format!("{}.atlas", path)).await {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                println!("Missing {}.atlas frame list, using procedural sprites", path);
+                return Sprites::procedural();
+            }
+        };
 
-fn play_enemy_defeat_sound() {
-    println!("🔊 Enemy defeated!");
-}
+        texture.set_filter(FilterMode::Nearest);
+        let frames = parse_atlas(&metadata);
+        println!("Loaded sprite atlas with {} frames", frames.len());
+        Sprites { atlas: Some(SpriteAtlas { texture, frames }) }
+    }
 
-fn play_powerup_sound() {
-    println!("🔊 Power-up collected!");
+    // Returns false when the frame isn't in the atlas so the caller can
+    // draw its procedural version instead
+    fn draw(&self, name: &str, canvas: SpriteCanvas) -> bool {
+        let Some(atlas) = &self.atlas else {
+            return false;
+        };
+        let Some(frame) = atlas.frames.get(name) else {
+            return false;
+        };
+        // Mirror the frame's offset along with the image
+        let offset_x = if canvas.flip_x {
+            canvas.width - frame.offset.x - frame.source.w
+        } else {
+            frame.offset.x
+        };
+        draw_texture_ex(
+            &atlas.texture,
+            canvas.x + offset_x,
+            canvas.y + frame.offset.y,
+            WHITE,
+            DrawTextureParams {
+                source: Some(frame.source),
+                flip_x: canvas.flip_x,
+                ..Default::default()
+            },
+        );
+        true
+    }
 }
 
-fn play_hit_sound() {
-    println!("🔊 Player hit!");
+impl SpriteCanvas {
+    fn new(x: f32, y: f32, width: f32) -> Self {
+        SpriteCanvas { x, y, width, flip_x: false }
+    }
+
+    fn flipped(x: f32, y: f32, width: f32, flip_x: bool) -> Self {
+        SpriteCanvas { x, y, width, flip_x }
+    }
+
+    fn rect(&self, offset_x: f32, offset_y: f32, width: f32, height: f32, color: Color) {
+        let offset_x = if self.flip_x { self.width - offset_x - width } else { offset_x };
+        draw_rectangle(self.x + offset_x, self.y + offset_y, width, height, color);
+    }
 }
 
-fn play_level_complete_sound() {
-    println!("🔊 Level complete!");
+impl AnimState {
+    fn clip_name(&self) -> &'static str {
+        match self {
+            AnimState::Idle => "idle",
+            AnimState::Walk => "walk",
+            AnimState::Run => "run",
+            AnimState::Jump => "jump",
+            AnimState::Fall => "fall",
+            AnimState::Hurt => "hurt",
+            AnimState::Die => "die",
+        }
+    }
 }
 
-impl Level {
-    fn create_level_1() -> Self {
-        // World 1-1: Basic tutorial level
-        let platforms = vec![
-            Platform::new(0.0, 550.0, 800.0, 50.0),       // Ground
-            Platform::new(200.0, 450.0, 150.0, 20.0),     // First platform
-            Platform::new(500.0, 400.0, 120.0, 20.0),     // Higher platform
-            Platform::new(100.0, 350.0, 100.0, 20.0),     // Side platform
-            Platform::new(650.0, 300.0, 150.0, 20.0),     // High platform
-            Platform::new(900.0, 500.0, 200.0, 20.0),     // Landing area
-            Platform::new(1200.0, 400.0, 150.0, 20.0),    // Challenge jump
-            Platform::new(1500.0, 350.0, 100.0, 20.0),    // Near goal
-            Platform::new(1700.0, 500.0, 200.0, 50.0),    // Goal platform
-        ];
-        
-        let enemies = vec![
-            Enemy::new(250.0, 420.0),
-            Enemy::new(550.0, 370.0),
-            Enemy::new(950.0, 470.0),
-            Enemy::new(1250.0, 370.0),
-        ];
-        
-        let coins = vec![
-            Coin::new(150.0, 520.0),
-            Coin::new(275.0, 430.0),
-            Coin::new(325.0, 430.0),
-            Coin::new(575.0, 380.0),
-            Coin::new(700.0, 280.0),
-            Coin::new(1000.0, 480.0),
-            Coin::new(1300.0, 380.0),
-            Coin::new(1550.0, 330.0),
-        ];
-        
-        let powerups = vec![
-            PowerUp::new(275.0, 420.0, PowerUpType::SpeedBoost),
-            PowerUp::new(750.0, 280.0, PowerUpType::JumpBoost),
-            PowerUp::new(1000.0, 470.0, PowerUpType::SpeedBoost),
-        ];
+impl Animator {
+    fn new(clips: &'static [AnimClip], state: AnimState) -> Self {
+        let mut animator = Animator { clips, state, clip: 0, frame: 0, timer: 0.0, flip_x: false };
+        animator.clip = animator.find_clip(state);
+        animator
+    }
 
-        Level {
-            platforms,
-            enemies,
-            coins,
-            powerups,
-            goal_x: 1800.0,
-            goal_y: 450.0,
-        }
+    fn find_clip(&self, state: AnimState) -> usize {
+        self.clips.iter()
+            .position(|clip| clip.name == state.clip_name())
+            .unwrap_or(0)
     }
 
-    fn create_level_2() -> Self {
-        // World 1-2: Underground level with more enemies
-        let platforms = vec![
-            Platform::new(0.0, 550.0, 300.0, 50.0),       // Start area
-            Platform::new(400.0, 500.0, 100.0, 20.0),     // First gap
-            Platform::new(600.0, 450.0, 100.0, 20.0),     // Rising platforms
-            Platform::new(800.0, 400.0, 100.0, 20.0),
-            Platform::new(1000.0, 350.0, 100.0, 20.0),
-            Platform::new(1200.0, 300.0, 150.0, 20.0),    // High platform
-            Platform::new(1450.0, 400.0, 100.0, 20.0),    // Drop down
-            Platform::new(1650.0, 500.0, 100.0, 20.0),
-            Platform::new(1850.0, 450.0, 100.0, 20.0),
-            Platform::new(2050.0, 400.0, 200.0, 50.0),    // Goal area
-        ];
-        
-        let enemies = vec![
-            Enemy::new(450.0, 470.0),
-            Enemy::new(650.0, 420.0),
-            Enemy::new(850.0, 370.0),
-            Enemy::new(1050.0, 320.0),
-            Enemy::new(1300.0, 270.0),
-            Enemy::new(1500.0, 370.0),
-            Enemy::new(1700.0, 470.0),
-        ];
-        
-        let coins = vec![
-            Coin::new(250.0, 520.0),
-            Coin::new(450.0, 480.0),
-            Coin::new(650.0, 430.0),
-            Coin::new(850.0, 380.0),
-            Coin::new(1050.0, 330.0),
-            Coin::new(1325.0, 280.0),
-            Coin::new(1500.0, 380.0),
-            Coin::new(1700.0, 480.0),
-            Coin::new(1900.0, 430.0),
-            Coin::new(2100.0, 380.0),
-        ];
-        
-        let powerups = vec![
-            PowerUp::new(425.0, 470.0, PowerUpType::JumpBoost),
-            PowerUp::new(1275.0, 270.0, PowerUpType::SpeedBoost),
-            PowerUp::new(1875.0, 420.0, PowerUpType::JumpBoost),
-        ];
+    // Switching state restarts the clip, staying in the same state doesn't
+    fn set_state(&mut self, state: AnimState) {
+        if state != self.state {
+            self.state = state;
+            self.clip = self.find_clip(state);
+            self.frame = 0;
+            self.timer = 0.0;
+        }
+    }
 
-        Level {
-            platforms,
-            enemies,
-            coins,
-            powerups,
-            goal_x: 2150.0,
-            goal_y: 350.0,
+    fn update(&mut self, dt: f32) {
+        let clip = &self.clips[self.clip];
+        self.timer += dt;
+        while self.timer >= clip.frames[self.frame].duration {
+            if self.frame + 1 < clip.frames.len() {
+                self.timer -= clip.frames[self.frame].duration;
+                self.frame += 1;
+            } else if clip.mode == PlayMode::Loop {
+                self.timer -= clip.frames[self.frame].duration;
+                self.frame = 0;
+            } else {
+                // One-shot clips stay on their last frame
+                self.timer = clip.frames[self.frame].duration;
+                break;
+            }
         }
     }
 
-    fn create_level_3() -> Self {
-        // World 1-3: Castle level with challenging jumps
-        let platforms = vec![
-            Platform::new(0.0, 550.0, 200.0, 50.0),       // Start
-            Platform::new(300.0, 500.0, 80.0, 20.0),      // Small platforms
-            Platform::new(480.0, 450.0, 80.0, 20.0),
-            Platform::new(660.0, 400.0, 80.0, 20.0),
-            Platform::new(840.0, 350.0, 80.0, 20.0),      // Ascending
-            Platform::new(1020.0, 300.0, 100.0, 20.0),    // Peak
-            Platform::new(1200.0, 250.0, 150.0, 20.0),    // High castle area
-            Platform::new(1450.0, 300.0, 80.0, 20.0),     // Descending
-            Platform::new(1630.0, 350.0, 80.0, 20.0),
-            Platform::new(1810.0, 400.0, 80.0, 20.0),
-            Platform::new(1990.0, 450.0, 80.0, 20.0),
-            Platform::new(2170.0, 500.0, 200.0, 50.0),    // Final area
-        ];
-        
-        let enemies = vec![
-            Enemy::new(350.0, 470.0),
-            Enemy::new(530.0, 420.0),
-            Enemy::new(710.0, 370.0),
-            Enemy::new(890.0, 320.0),
-            Enemy::new(1070.0, 270.0),
-            Enemy::new(1300.0, 220.0),
-            Enemy::new(1500.0, 270.0),
-            Enemy::new(1680.0, 320.0),
-            Enemy::new(1860.0, 370.0),
-            Enemy::new(2040.0, 420.0),
-        ];
-        
-        let coins = vec![
-            Coin::new(340.0, 480.0),
-            Coin::new(520.0, 430.0),
-            Coin::new(700.0, 380.0),
-            Coin::new(880.0, 330.0),
-            Coin::new(1070.0, 280.0),
-            Coin::new(1325.0, 230.0),
-            Coin::new(1490.0, 280.0),
-            Coin::new(1670.0, 330.0),
-            Coin::new(1850.0, 380.0),
-            Coin::new(2030.0, 430.0),
-            Coin::new(2220.0, 480.0),
-            Coin::new(2270.0, 480.0),
-        ];
-        
-        let powerups = vec![
-            PowerUp::new(380.0, 470.0, PowerUpType::JumpBoost),
-            PowerUp::new(1325.0, 220.0, PowerUpType::SpeedBoost),
-            PowerUp::new(2220.0, 470.0, PowerUpType::JumpBoost),
-        ];
+    fn finished(&self) -> bool {
+        let clip = &self.clips[self.clip];
+        clip.mode == PlayMode::Once &&
+        self.frame + 1 == clip.frames.len() &&
+        self.timer >= clip.frames[self.frame].duration
+    }
 
-        Level {
-            platforms,
-            enemies,
-            coins,
-            powerups,
-            goal_x: 2300.0,
-            goal_y: 450.0,
-        }
+    fn sprite(&self) -> &'static str {
+        self.clips[self.clip].frames[self.frame].sprite
     }
+}
 
-    fn draw_goal(&self) {
-        // Draw flag pole goal (Mario-style)
-        // Pole
-        draw_rectangle(self.goal_x, self.goal_y - 100.0, 8.0, 150.0, BROWN);
-        // Flag
-        draw_rectangle(self.goal_x + 8.0, self.goal_y - 80.0, 40.0, 30.0, GREEN);
-        draw_rectangle(self.goal_x + 8.0, self.goal_y - 50.0, 40.0, 30.0, RED);
-        // Flag pole top
-        draw_rectangle(self.goal_x + 2.0, self.goal_y - 105.0, 12.0, 8.0, GOLD);
+// One frame per line: `name x y width height [offset_x offset_y]`,
+// blank lines and lines starting with # are ignored
+fn parse_atlas(metadata: &str) -> HashMap<String, SpriteFrame> {
+    let mut frames = HashMap::new();
+    for (line_number, line) in metadata.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let name = parts.next().unwrap_or_default();
+        let numbers: Result<Vec<f32>, _> = parts.map(|part| part.parse::<f32>()).collect();
+        match numbers.as_deref() {
+            Ok([x, y, width, height]) => {
+                frames.insert(name.to_string(), SpriteFrame {
+                    source: Rect::new(*x, *y, *width, *height),
+                    offset: Vec2::ZERO,
+                });
+            }
+            Ok([x, y, width, height, offset_x, offset_y]) => {
+                frames.insert(name.to_string(), SpriteFrame {
+                    source: Rect::new(*x, *y, *width, *height),
+                    offset: vec2(*offset_x, *offset_y),
+                });
+            }
+            _ => println!("Skipping bad atlas line {}: {}", line_number + 1, line),
+        }
     }
+    frames
 }
 
-impl Game {
-    fn new() -> Self {
-        let levels = vec![
-            Level::create_level_1(),
-            Level::create_level_2(),
-            Level::create_level_3(),
-        ];
+impl Particles {
+    fn new(capacity: usize) -> Self {
+        let dead = Particle {
+            x: 0.0,
+            y: 0.0,
+            velocity_x: 0.0,
+            velocity_y: 0.0,
+            age: 0.0,
+            lifetime: 0.0,
+            size: 0.0,
+            def: &DUST,
+        };
+        Particles { pool: vec![dead; capacity], next: 0, trail_timer: 0.0 }
+    }
 
-        Game {
-            state: GameState::StartScreen,
-            player: Player::new(),
-            camera: Camera::new(),
-            levels,
-            current_level: 0,
-            level_completed: false,
+    fn clear(&mut self) {
+        for particle in &mut self.pool {
+            particle.lifetime = 0.0;
         }
     }
 
-    fn reset_game(&mut self) {
-        self.player = Player::new();
-        self.camera = Camera::new();
-        self.current_level = 0;
-        self.level_completed = false;
-        
-        // Reset all levels
-        self.levels = vec![
-            Level::create_level_1(),
-            Level::create_level_2(),
-            Level::create_level_3(),
-        ];
+    fn spawn(&mut self, def: &'static EmitterDef, x: f32, y: f32) {
+        // Take the next slot in the ring, overwriting the oldest effect
+        // when the pool is full
+        let angle = rand::gen_range(def.angle.0, def.angle.1).to_radians();
+        let speed = rand::gen_range(def.speed.0, def.speed.1);
+        self.pool[self.next] = Particle {
+            x,
+            y,
+            velocity_x: angle.cos() * speed,
+            velocity_y: angle.sin() * speed,
+            age: 0.0,
+            lifetime: rand::gen_range(def.lifetime.0, def.lifetime.1),
+            size: rand::gen_range(def.size.0, def.size.1),
+            def,
+        };
+        self.next = (self.next + 1) % self.pool.len();
     }
 
-    fn current_level(&self) -> &Level {
-        &self.levels[self.current_level]
+    fn burst(&mut self, def: &'static EmitterDef, x: f32, y: f32) {
+        for _ in 0..def.burst {
+            self.spawn(def, x, y);
+        }
     }
 
-    fn current_level_mut(&mut self) -> &mut Level {
-        &mut self.levels[self.current_level]
+    // Bursts for gameplay events; dust and trails are emitted by the level itself
+    fn observe(&mut self, event: &GameEvent) {
+        let (def, at) = match *event {
+            GameEvent::CoinCollected { at } => (&SPARKLE, at),
+            GameEvent::EnemyStomped { at, .. } => (&POOF, at),
+            GameEvent::BrickSmashed { at } => (&DEBRIS, at),
+            _ => return,
+        };
+        let center = at.center();
+        self.burst(def, center.x, center.y);
+    }
+
+    // Continuous effects spawn at the emitter's rate while this is called
+    fn emit(&mut self, def: &'static EmitterDef, x: f32, y: f32, dt: f32) {
+        self.trail_timer += dt * def.rate;
+        while self.trail_timer >= 1.0 {
+            self.trail_timer -= 1.0;
+            self.spawn(def, x, y);
+        }
     }
 
     fn update(&mut self, dt: f32) {
-        match self.state {
-            GameState::StartScreen => {
-                if is_key_pressed(KeyCode::Space) || is_key_pressed(KeyCode::Enter) {
-                    self.state = GameState::Playing;
-                }
+        for particle in &mut self.pool {
+            if particle.age >= particle.lifetime {
+                continue;
             }
-            GameState::Playing => {
-                if is_key_pressed(KeyCode::Escape) {
-                    self.state = GameState::Paused;
-                    return;
-                }
+            particle.age += dt;
+            particle.velocity_y += particle.def.gravity * dt;
+            particle.x += particle.velocity_x * dt;
+            particle.y += particle.velocity_y * dt;
+        }
+    }
 
-                let jumped = self.player.handle_input();
-                if jumped {
-                    play_jump_sound();
-                }
-                // Update player first (before any level mutable borrows)
-                let platforms = &self.current_level().platforms.clone();
-                self.player.update(dt, &platforms);
-                
-                // Extract current player state for collision detection
-                let player_x = self.player.x;
-                let player_y = self.player.y;
-                let player_width = self.player.width;
-                let player_height = self.player.height;
-                let player_velocity_y = self.player.velocity_y;
-                
-                // Collect all changes to apply later
-                let mut player_velocity_bounce = None;
-                let mut score_change = 0;
-                let mut lives_change = 0;
-                let mut speed_boost = None;
-                let mut jump_boost = None;
-                let mut should_reset_position = false;
-                
-                // Handle enemy collisions
-                {
-                    let level = self.current_level_mut();
-                    
-                    for enemy in &mut level.enemies {
-                        enemy.update(dt, &level.platforms);
-                        
-                        if enemy.alive && 
-                           enemy.x < player_x + player_width &&
-                           enemy.x + enemy.width > player_x &&
-                           enemy.y < player_y + player_height &&
-                           enemy.y + enemy.height > player_y {
-                            
-                            if player_velocity_y > 0.0 && player_y < enemy.y {
-                                enemy.alive = false;
-                                player_velocity_bounce = Some(-300.0);
-                                score_change += 100;
-                                play_enemy_defeat_sound();
-                            } else {
-                                lives_change -= 1;
-                                should_reset_position = true;
-                                play_hit_sound();
-                            }
-                        }
-                    }
-                }
-                
-                // Handle coin collection
-                {
-                    let level = self.current_level_mut();
-                    
-                    for coin in &mut level.coins {
-                        if !coin.collected &&
-                           coin.x < player_x + player_width &&
-                           coin.x + coin.width > player_x &&
-                           coin.y < player_y + player_height &&
-                           coin.y + coin.height > player_y {
-                            
-                            coin.collected = true;
-                            score_change += 10;
-                            play_coin_sound();
-                        }
-                    }
-                }
-                
-                // Handle power-up collection
-                {
-                    let level = self.current_level_mut();
-                    
-                    for powerup in &mut level.powerups {
-                        if !powerup.collected &&
-                           powerup.x < player_x + player_width &&
-                           powerup.x + powerup.width > player_x &&
-                           powerup.y < player_y + player_height &&
-                           powerup.y + powerup.height > player_y {
-                            
-                            powerup.collected = true;
-                            match powerup.power_type {
-                                PowerUpType::SpeedBoost => speed_boost = Some(1.5),
-                                PowerUpType::JumpBoost => jump_boost = Some(1.3),
-                            }
-                            score_change += 50;
-                            play_powerup_sound();
-                        }
-                    }
-                }
-                
-                // Apply all changes to player (after releasing level borrow)
-                if let Some(velocity) = player_velocity_bounce {
-                    self.player.velocity_y = velocity;
-                }
-                self.player.score += score_change;
-                self.player.lives += lives_change;
-                if let Some(boost) = speed_boost {
-                    self.player.speed_boost = boost;
-                }
-                if let Some(boost) = jump_boost {
-                    self.player.jump_boost = boost;
-                }
-                if should_reset_position {
-                    self.player.reset_position();
-                }
-                if self.player.lives <= 0 {
-                    self.state = GameState::GameOver;
-                }
+    fn draw(&self) {
+        for particle in &self.pool {
+            if particle.age >= particle.lifetime {
+                continue;
+            }
+            let color = sample_ramp(particle.def.colors, particle.age / particle.lifetime);
+            let half = particle.size / 2.0;
+            draw_rectangle(particle.x - half, particle.y - half, particle.size, particle.size, color);
+        }
+    }
+}
 
-                // Check goal collision
-                let level = self.current_level();
-                if self.player.x + self.player.width > level.goal_x &&
-                   self.player.x < level.goal_x + 50.0 &&
-                   self.player.y + self.player.height > level.goal_y - 100.0 &&
-                   self.player.y < level.goal_y + 50.0 {
-                    
-                    if !self.level_completed {
-                        self.level_completed = true;
-                        play_level_complete_sound();
-                        self.player.score += 1000;
-                        
-                        // Move to next level after 2 seconds
-                        if self.current_level + 1 < self.levels.len() {
-                            self.current_level += 1;
-                            self.player.reset_position();
-                            self.level_completed = false;
-                        } else {
-                            // Game completed
-                            self.state = GameState::GameOver;
-                        }
-                    }
-                }
+impl Popups {
+    const LIFETIME: f32 = 0.8;
 
-                // Check if player fell off the world
-                if self.player.y > 700.0 {
-                    self.player.lives -= 1;
-                    play_hit_sound();
-                    if self.player.lives <= 0 {
-                        self.state = GameState::GameOver;
-                    } else {
-                        self.player.reset_position();
-                    }
-                }
-                
-                self.camera.follow_player(&self.player);
+    fn new(capacity: usize) -> Self {
+        let dead = Popup { x: 0.0, y: 0.0, age: Popups::LIFETIME, label: String::new(), color: WHITE };
+        Popups { pool: vec![dead; capacity], next: 0 }
+    }
+
+    fn clear(&mut self) {
+        for popup in &mut self.pool {
+            popup.age = Popups::LIFETIME;
+        }
+    }
+
+    fn spawn(&mut self, text: PopupText, x: f32, y: f32) {
+        let popup = &mut self.pool[self.next];
+        popup.x = x;
+        popup.y = y;
+        popup.age = 0.0;
+        popup.label.clear();
+        popup.color = match text {
+            PopupText::Points(points) => {
+                let _ = write!(popup.label, "{}", points);
+                WHITE
             }
-            GameState::Paused => {
-                if is_key_pressed(KeyCode::Escape) {
-                    self.state = GameState::Playing;
-                }
+            PopupText::OneUp => {
+                popup.label.push_str("1UP");
+                Color::new(0.3, 1.0, 0.3, 1.0)
             }
-            GameState::GameOver => {
-                if is_key_pressed(KeyCode::R) {
-                    self.reset_game();
-                    self.state = GameState::Playing;
-                } else if is_key_pressed(KeyCode::Escape) {
-                    self.state = GameState::StartScreen;
-                }
+        };
+        self.next = (self.next + 1) % self.pool.len();
+    }
+
+    fn update(&mut self, dt: f32) {
+        for popup in &mut self.pool {
+            if popup.age < Popups::LIFETIME {
+                popup.age += dt;
+                popup.y -= 40.0 * dt;
             }
         }
     }
 
     fn draw(&self) {
-        // Background gradient from light blue to darker blue
-        for y in 0..600 {
-            let ratio = y as f32 / 600.0;
-            let color = Color::new(
-                0.5 + ratio * 0.2,  // Light blue to darker blue
-                0.8 + ratio * 0.1,  // Sky blue gradient
-                1.0,                // Full blue
-                1.0
-            );
-            draw_rectangle(0.0, y as f32, 800.0, 1.0, color);
-        }
-        
-        // Add simple clouds (only in playing state to avoid camera issues)
-        if self.state == GameState::Playing {
-            self.camera.apply();
-            self.draw_clouds();
-            set_default_camera();
-        }
-
-        match self.state {
-            GameState::StartScreen => {
-                draw_text("SLACK GAME", 320.0, 250.0, 60.0, WHITE);
-                draw_text("Press SPACE or ENTER to start", 250.0, 350.0, 30.0, WHITE);
-                draw_text("Use A/D or Arrow Keys to move", 240.0, 400.0, 25.0, WHITE);
-                draw_text("Use W/Up/Space to jump", 260.0, 430.0, 25.0, WHITE);
-            }
-            GameState::Playing => {
-                self.camera.apply();
-                
-                let level = self.current_level();
-                
-                for platform in &level.platforms {
-                    platform.draw();
-                }
-                
-                for enemy in &level.enemies {
-                    enemy.draw();
-                }
-                
-                for coin in &level.coins {
-                    coin.draw();
-                }
-                
-                for powerup in &level.powerups {
-                    powerup.draw();
-                }
-                
-                // Draw goal flag
-                level.draw_goal();
-                
-                self.player.draw();
-                
-                set_default_camera();
-                draw_text(&format!("Score: {}", self.player.score), 10.0, 30.0, 30.0, WHITE);
-                draw_text(&format!("Lives: {}", self.player.lives), 10.0, 60.0, 30.0, WHITE);
-                draw_text(&format!("Level: {}", self.current_level + 1), 10.0, 90.0, 30.0, WHITE);
-                draw_text("ESC: Pause", 10.0, 120.0, 20.0, WHITE);
-            }
-            GameState::Paused => {
-                self.camera.apply();
-                
-                let level = self.current_level();
-                
-                for platform in &level.platforms {
-                    platform.draw();
-                }
-                
-                for enemy in &level.enemies {
-                    enemy.draw();
-                }
-                
-                for coin in &level.coins {
-                    coin.draw();
-                }
-                
-                for powerup in &level.powerups {
-                    powerup.draw();
-                }
-                
-                level.draw_goal();
-                
-                self.player.draw();
-                
-                set_default_camera();
-                draw_rectangle(0.0, 0.0, 800.0, 600.0, Color::new(0.0, 0.0, 0.0, 0.5));
-                draw_text("PAUSED", 340.0, 280.0, 60.0, WHITE);
-                draw_text("Press ESC to resume", 290.0, 340.0, 30.0, WHITE);
-            }
-            GameState::GameOver => {
-                if self.current_level >= self.levels.len() {
-                    // Game completed
-                    draw_text("CONGRATULATIONS!", 260.0, 200.0, 50.0, GOLD);
-                    draw_text("YOU COMPLETED ALL LEVELS!", 220.0, 260.0, 40.0, WHITE);
-                    draw_text(&format!("Final Score: {}", self.player.score), 280.0, 320.0, 40.0, WHITE);
-                } else {
-                    // Game over
-                    draw_text("GAME OVER", 310.0, 250.0, 60.0, RED);
-                    draw_text(&format!("Final Score: {}", self.player.score), 280.0, 320.0, 40.0, WHITE);
-                }
-                draw_text("Press R to restart", 300.0, 380.0, 30.0, WHITE);
-                draw_text("Press ESC for main menu", 270.0, 420.0, 30.0, WHITE);
+        for popup in &self.pool {
+            if popup.age >= Popups::LIFETIME {
+                continue;
             }
+            // Fade out over the last half of the popup's life
+            let alpha = ((Popups::LIFETIME - popup.age) / (Popups::LIFETIME / 2.0)).min(1.0);
+            let color = Color { a: alpha, ..popup.color };
+            let width = measure_text(&popup.label, None, 20, 1.0).width;
+            draw_text(&popup.label, popup.x - width / 2.0, popup.y, 20.0, color);
         }
     }
-    
-    fn draw_clouds(&self) {
-        // Simple cloud sprites scattered across the level
-        let clouds = [
-            (300.0, 100.0),
-            (800.0, 150.0),
-            (1400.0, 80.0),
-            (600.0, 200.0),
-            (1100.0, 120.0),
-            (1700.0, 160.0),
-        ];
-        
-        for (x, y) in clouds.iter() {
-            self.draw_cloud(*x, *y);
-        }
-    }
-    
-    fn draw_cloud(&self, x: f32, y: f32) {
-        // Simple cloud made of circles
-        draw_rectangle(x, y + 10.0, 60.0, 20.0, WHITE);
-        draw_rectangle(x - 10.0, y + 15.0, 30.0, 15.0, WHITE);
-        draw_rectangle(x + 40.0, y + 15.0, 30.0, 15.0, WHITE);
-        draw_rectangle(x + 10.0, y, 40.0, 20.0, WHITE);
-        draw_rectangle(x + 20.0, y - 5.0, 20.0, 15.0, WHITE);
+}
+
+fn sample_ramp(colors: &[Color], t: f32) -> Color {
+    if colors.len() == 1 {
+        return colors[0];
     }
+    let position = t.clamp(0.0, 1.0) * (colors.len() - 1) as f32;
+    let index = (position as usize).min(colors.len() - 2);
+    let blend = position - index as f32;
+    let (from, to) = (colors[index], colors[index + 1]);
+    Color::new(
+        from.r + (to.r - from.r) * blend,
+        from.g + (to.g - from.g) * blend,
+        from.b + (to.b - from.b) * blend,
+        from.a + (to.a - from.a) * blend,
+    )
 }
 
-#[macroquad::main("Slack Game")]
-async fn main() {
-    let mut game = Game::new();
-    
-    loop {
-        let dt = get_frame_time();
-        
-        game.update(dt);
-        game.draw();
+// Stable pseudo-random value in 0..1 so repeats don't all look the same
+fn slot_variation(slot: i32) -> f32 {
+    let hash = (slot as u32).wrapping_mul(2654435761) >> 16;
+    (hash % 1000) as f32 / 1000.0
+}
 
-        next_frame().await
+fn draw_layer_motif(kind: LayerKind, x: f32, y: f32, variation: f32) {
+    match kind {
+        LayerKind::Hills => {
+            // Stepped hill rising from the base line
+            let width = 260.0 + variation * 120.0;
+            let steps = 6;
+            for step in 0..steps {
+                let inset = step as f32 * width / (steps as f32 * 2.5);
+                draw_rectangle(x + inset, y - step as f32 * 18.0 - 18.0, width - inset * 2.0, 18.0, Color::new(0.45, 0.7, 0.5, 1.0));
+            }
+            draw_rectangle(x, y, width, 600.0, Color::new(0.45, 0.7, 0.5, 1.0));
+        }
+        LayerKind::Clouds => {
+            draw_cloud(x + variation * 120.0, y + variation * 90.0 - 40.0);
+        }
+        LayerKind::Bushes => {
+            let width = 60.0 + variation * 60.0;
+            draw_rectangle(x, y - 20.0, width, 20.0, Color::new(0.2, 0.6, 0.25, 1.0));
+            draw_rectangle(x + 10.0, y - 32.0, width - 20.0, 12.0, Color::new(0.2, 0.6, 0.25, 1.0));
+            draw_rectangle(x + 16.0, y - 28.0, 6.0, 4.0, Color::new(0.35, 0.75, 0.4, 1.0));
+        }
+        LayerKind::RockPillars => {
+            let height = 180.0 + variation * 220.0;
+            draw_rectangle(x, y - height, 70.0, height, Color::new(0.18, 0.14, 0.22, 1.0));
+            draw_rectangle(x + 10.0, y - height, 12.0, height, Color::new(0.23, 0.18, 0.28, 1.0));
+        }
+        LayerKind::Stalactites => {
+            let length = 40.0 + variation * 80.0;
+            let color = Color::new(0.25, 0.2, 0.3, 1.0);
+            draw_rectangle(x, y, 30.0, length * 0.4, color);
+            draw_rectangle(x + 6.0, y, 18.0, length * 0.75, color);
+            draw_rectangle(x + 12.0, y, 6.0, length, color);
+        }
+        LayerKind::CastleTowers => {
+            let height = 200.0 + variation * 160.0;
+            let stone = Color::new(0.18, 0.1, 0.16, 1.0);
+            draw_rectangle(x, y - height, 80.0, height + 600.0, stone);
+            // Battlements
+            for merlon in 0..4 {
+                draw_rectangle(x + merlon as f32 * 22.0, y - height - 14.0, 14.0, 14.0, stone);
+            }
+            // Lit window
+            draw_rectangle(x + 32.0, y - height + 40.0, 16.0, 26.0, Color::new(0.9, 0.6, 0.2, 1.0));
+        }
+        LayerKind::Banners => {
+            let length = 70.0 + variation * 50.0;
+            draw_rectangle(x, y, 4.0, 20.0, DARKGRAY);
+            draw_rectangle(x - 14.0, y + 20.0, 32.0, length, Color::new(0.55, 0.05, 0.1, 1.0));
+            draw_rectangle(x - 6.0, y + 30.0, 16.0, 16.0, GOLD);
+        }
+        LayerKind::CloudBank => {
+            let height = 30.0 + variation * 30.0;
+            draw_rectangle(x - 10.0, y - height, 220.0, height + 600.0, WHITE);
+            draw_rectangle(x + 30.0, y - height - 18.0, 100.0, 18.0, WHITE);
+        }
+        LayerKind::SnowyHills => {
+            let width = 260.0 + variation * 120.0;
+            let steps = 6;
+            for step in 0..steps {
+                let inset = step as f32 * width / (steps as f32 * 2.5);
+                let color = if step >= steps - 2 { WHITE } else { Color::new(0.55, 0.7, 0.8, 1.0) };
+                draw_rectangle(x + inset, y - step as f32 * 18.0 - 18.0, width - inset * 2.0, 18.0, color);
+            }
+            draw_rectangle(x, y, width, 600.0, Color::new(0.55, 0.7, 0.8, 1.0));
+        }
+    }
+}
+
+fn draw_coin_icon(position: Vec2, scale: f32) {
+    let Vec2 { x, y } = position;
+    draw_rectangle(x + 2.0 * scale, y + 2.0 * scale, 12.0 * scale, 12.0 * scale, GOLD);
+    draw_rectangle(x + 4.0 * scale, y + 4.0 * scale, 8.0 * scale, 8.0 * scale, YELLOW);
+    draw_rectangle(x + 7.0 * scale, y + 5.0 * scale, 2.0 * scale, 6.0 * scale, GOLD);
+}
+
+fn draw_cloud(x: f32, y: f32) {
+    // Simple cloud made of circles
+    draw_rectangle(x, y + 10.0, 60.0, 20.0, WHITE);
+    draw_rectangle(x - 10.0, y + 15.0, 30.0, 15.0, WHITE);
+    draw_rectangle(x + 40.0, y + 15.0, 30.0, 15.0, WHITE);
+    draw_rectangle(x + 10.0, y, 40.0, 20.0, WHITE);
+    draw_rectangle(x + 20.0, y - 5.0, 20.0, 15.0, WHITE);
+}
+
+impl Bundle {
+    fn player(x: f32, y: f32) -> Self {
+        Bundle {
+            transform: Transform { x, y },
+            collider: Collider { width: 32.0, height: 32.0 },
+            velocity: Some(Velocity::default()),
+            body: Some(Body::default()),
+            sprite: Some(Sprite { kind: SpriteKind::Player, animator: Some(Animator::new(PLAYER_CLIPS, AnimState::Idle)) }),
+            ..Bundle::default()
+        }
+    }
+
+    fn enemy(x: f32, y: f32) -> Self {
+        Bundle {
+            transform: Transform { x, y },
+            collider: Collider { width: 24.0, height: 24.0 },
+            velocity: Some(Velocity::default()),
+            sprite: Some(Sprite { kind: SpriteKind::Enemy, animator: Some(Animator::new(ENEMY_CLIPS, AnimState::Walk)) }),
+            hostile: Some(Hostile),
+            patrol: Some(Patrol { speed: 50.0, direction: 1.0 }),
+            ..Bundle::default()
+        }
+    }
+
+    fn coin(x: f32, y: f32) -> Self {
+        Bundle {
+            transform: Transform { x, y },
+            collider: Collider { width: 16.0, height: 16.0 },
+            sprite: Some(Sprite { kind: SpriteKind::Coin, animator: None }),
+            pickup: Some(Pickup::Coin),
+            ..Bundle::default()
+        }
+    }
+
+    fn powerup(x: f32, y: f32, power_type: PowerUpType) -> Self {
+        Bundle {
+            transform: Transform { x, y },
+            collider: Collider { width: 20.0, height: 20.0 },
+            sprite: Some(Sprite { kind: SpriteKind::PowerUp(power_type), animator: None }),
+            pickup: Some(Pickup::PowerUp(power_type)),
+            ..Bundle::default()
+        }
+    }
+}
+
+impl Body {
+    fn anim_state(&self, velocity: Velocity) -> AnimState {
+        if !self.on_ground {
+            if velocity.y < 0.0 { AnimState::Jump } else { AnimState::Fall }
+        } else if velocity.x.abs() > 250.0 {
+            // Only reachable with a speed boost
+            AnimState::Run
+        } else if velocity.x.abs() > 10.0 {
+            AnimState::Walk
+        } else {
+            AnimState::Idle
+        }
+    }
+}
+
+impl SpriteKind {
+    fn draw(self, at: Rect, animator: Option<&Animator>, sprites: &Sprites, palette: &Palette) {
+        match self {
+            SpriteKind::Player => draw_player(at, animator, sprites),
+            SpriteKind::Enemy => draw_enemy(at, animator, sprites, palette),
+            SpriteKind::Coin => draw_coin(at, sprites),
+            SpriteKind::PowerUp(power_type) => draw_powerup(at, power_type, sprites),
+        }
+    }
+}
+
+fn draw_player(at: Rect, animator: Option<&Animator>, sprites: &Sprites) {
+    let flip_x = animator.is_some_and(|animator| animator.flip_x);
+    let canvas = SpriteCanvas::flipped(at.x, at.y, at.w, flip_x);
+    let sprite = animator.map_or("player_idle", Animator::sprite);
+    if !sprites.draw(sprite, canvas) {
+        Player::draw_procedural(sprite, canvas);
+    }
+}
+
+fn draw_enemy(at: Rect, animator: Option<&Animator>, sprites: &Sprites, palette: &Palette) {
+    let flip_x = animator.is_some_and(|animator| animator.flip_x);
+    let canvas = SpriteCanvas::flipped(at.x, at.y, at.w, flip_x);
+    let sprite = animator.map_or("enemy_walk_0", Animator::sprite);
+    if sprites.draw(sprite, canvas) {
+        return;
+    }
+
+    if sprite == "enemy_squashed" {
+        // Flattened body with closed eyes
+        canvas.rect(0.0, 16.0, 24.0, 8.0, palette.enemy_body);
+        canvas.rect(4.0, 14.0, 16.0, 4.0, palette.enemy_head);
+        canvas.rect(6.0, 15.0, 4.0, 1.0, palette.enemy_eyes);
+        canvas.rect(14.0, 15.0, 4.0, 1.0, palette.enemy_eyes);
+        return;
+    }
+
+    // Draw simple enemy sprite
+    // Body
+    canvas.rect(2.0, 8.0, 20.0, 16.0, palette.enemy_body);
+    // Head
+    canvas.rect(4.0, 0.0, 16.0, 12.0, palette.enemy_head);
+    // Eyes, looking the way it walks
+    canvas.rect(8.0, 3.0, 3.0, 3.0, palette.enemy_eyes);
+    canvas.rect(15.0, 3.0, 3.0, 3.0, palette.enemy_eyes);
+    // Feet take turns stepping
+    if sprite == "enemy_walk_0" {
+        canvas.rect(0.0, 20.0, 6.0, 4.0, BLACK);
+        canvas.rect(18.0, 21.0, 6.0, 3.0, BLACK);
+    } else {
+        canvas.rect(0.0, 21.0, 6.0, 3.0, BLACK);
+        canvas.rect(18.0, 20.0, 6.0, 4.0, BLACK);
+    }
+}
+
+fn draw_coin(at: Rect, sprites: &Sprites) {
+    if !sprites.draw("coin", SpriteCanvas::new(at.x, at.y, at.w)) {
+        // Draw coin with sparkle effect
+        draw_rectangle(at.x + 2.0, at.y + 2.0, 12.0, 12.0, GOLD);
+        draw_rectangle(at.x + 4.0, at.y + 4.0, 8.0, 8.0, YELLOW);
+        // Add sparkle
+        draw_rectangle(at.x + 1.0, at.y + 7.0, 2.0, 2.0, WHITE);
+        draw_rectangle(at.x + 13.0, at.y + 7.0, 2.0, 2.0, WHITE);
+    }
+}
+
+fn draw_powerup(at: Rect, power_type: PowerUpType, sprites: &Sprites) {
+    let name = match power_type {
+        PowerUpType::SpeedBoost => "powerup_speed",
+        PowerUpType::JumpBoost => "powerup_jump",
+    };
+    if !sprites.draw(name, SpriteCanvas::new(at.x, at.y, at.w)) {
+        let (primary_color, secondary_color) = match power_type {
+            PowerUpType::SpeedBoost => (ORANGE, RED),
+            PowerUpType::JumpBoost => (BLUE, SKYBLUE),
+        };
+        // Draw power-up with glow effect
+        draw_rectangle(at.x - 2.0, at.y - 2.0, at.w + 4.0, at.h + 4.0, secondary_color);
+        draw_rectangle(at.x, at.y, at.w, at.h, primary_color);
+        draw_rectangle(at.x + 4.0, at.y + 4.0, 12.0, 12.0, WHITE);
+        
+        // Add symbol
+        match power_type {
+            PowerUpType::SpeedBoost => {
+                // Lightning bolt
+                draw_rectangle(at.x + 8.0, at.y + 6.0, 2.0, 8.0, WHITE);
+                draw_rectangle(at.x + 6.0, at.y + 8.0, 6.0, 2.0, WHITE);
+            }
+            PowerUpType::JumpBoost => {
+                // Up arrow
+                draw_rectangle(at.x + 9.0, at.y + 6.0, 2.0, 6.0, WHITE);
+                draw_rectangle(at.x + 7.0, at.y + 8.0, 6.0, 2.0, WHITE);
+            }
+        }
+    }
+}
+
+// Strict overlap, so rectangles that only share an edge don't touch
+fn overlapping(a: Rect, b: Rect) -> bool {
+    a.x < b.x + b.w &&
+    a.x + a.w > b.x &&
+    a.y < b.y + b.h &&
+    a.y + a.h > b.y
+}
+
+// Gameplay actions the player can rebind
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Left,
+    Right,
+    Up, // Jumps and enters doors
+    Down, // Enters pipes
+    Jump,
+}
+
+impl Action {
+    const ALL: [Action; 5] = [Action::Left, Action::Right, Action::Up, Action::Down, Action::Jump];
+
+    fn label(self) -> &'static str {
+        match self {
+            Action::Left => "Move Left",
+            Action::Right => "Move Right",
+            Action::Up => "Up / Door",
+            Action::Down => "Down / Pipe",
+            Action::Jump => "Jump",
+        }
+    }
+
+    // Arrow keys always work alongside the bound key
+    fn arrow_key(self) -> Option<KeyCode> {
+        match self {
+            Action::Left => Some(KeyCode::Left),
+            Action::Right => Some(KeyCode::Right),
+            Action::Up => Some(KeyCode::Up),
+            Action::Down => Some(KeyCode::Down),
+            Action::Jump => None,
+        }
+    }
+
+    fn pad_button(self) -> PadButton {
+        match self {
+            Action::Left => PadButton::Left,
+            Action::Right => PadButton::Right,
+            Action::Up => PadButton::Up,
+            Action::Down => PadButton::Down,
+            Action::Jump => PadButton::South,
+        }
+    }
+}
+
+// Keys offered when rebinding; also used to read bindings back from the save file
+const BINDABLE_KEYS: [KeyCode; 48] = [
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+    KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::Space, KeyCode::Tab, KeyCode::LeftShift, KeyCode::RightShift,
+    KeyCode::LeftControl, KeyCode::RightControl, KeyCode::LeftAlt, KeyCode::RightAlt,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Semicolon,
+];
+
+fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS.iter().copied().find(|&key| key_name(key) == name)
+}
+
+#[derive(Clone, Copy)]
+struct KeyBindings {
+    keys: [KeyCode; 5], // Indexed by Action
+}
+
+impl KeyBindings {
+    fn standard() -> Self {
+        KeyBindings {
+            keys: [KeyCode::A, KeyCode::D, KeyCode::W, KeyCode::S, KeyCode::Space],
+        }
+    }
+
+    fn key(&self, action: Action) -> KeyCode {
+        self.keys[action as usize]
+    }
+
+    // Taking a key another action uses swaps the two, so no key ever does two things
+    fn set(&mut self, action: Action, key: KeyCode) {
+        let previous = self.key(action);
+        if let Some(other) = self.keys.iter().position(|&bound| bound == key) {
+            self.keys[other] = previous;
+        }
+        self.keys[action as usize] = key;
+    }
+}
+
+// Buttons read from the first connected gamepad, stored as bit flags
+#[derive(Clone, Copy)]
+enum PadButton {
+    Up = 1,
+    Down = 2,
+    Left = 4,
+    Right = 8,
+    South = 16, // A on Xbox layouts, cross on PlayStation
+    East = 32,
+    Start = 64,
+}
+
+struct Gamepad {
+    held: u8,
+    previous: u8,
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Gamepad {
+    fn new() -> Self {
+        Gamepad {
+            held: 0,
+            previous: 0,
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new().ok(),
+        }
+    }
+
+    fn poll(&mut self) {
+        self.previous = self.held;
+        self.held = self.read();
+    }
+
+    #[cfg(feature = "gamepad")]
+    fn read(&mut self) -> u8 {
+        use gilrs::{Axis, Button};
+
+        let Some(gilrs) = &mut self.gilrs else {
+            return 0;
+        };
+        // Draining events keeps gilrs' connection and button state current
+        while gilrs.next_event().is_some() {}
+        let Some((_, pad)) = gilrs.gamepads().next() else {
+            return 0;
+        };
+
+        let stick_x = pad.value(Axis::LeftStickX);
+        let stick_y = pad.value(Axis::LeftStickY);
+        let buttons = [
+            (PadButton::Up, pad.is_pressed(Button::DPadUp) || stick_y > 0.5),
+            (PadButton::Down, pad.is_pressed(Button::DPadDown) || stick_y < -0.5),
+            (PadButton::Left, pad.is_pressed(Button::DPadLeft) || stick_x < -0.5),
+            (PadButton::Right, pad.is_pressed(Button::DPadRight) || stick_x > 0.5),
+            (PadButton::South, pad.is_pressed(Button::South)),
+            (PadButton::East, pad.is_pressed(Button::East)),
+            (PadButton::Start, pad.is_pressed(Button::Start)),
+        ];
+        buttons.iter()
+            .filter(|(_, down)| *down)
+            .fold(0, |held, (button, _)| held | *button as u8)
+    }
+
+    // Built without the gamepad feature, only the keyboard is read
+    #[cfg(not(feature = "gamepad"))]
+    fn read(&mut self) -> u8 {
+        0
+    }
+
+    fn down(&self, button: PadButton) -> bool {
+        self.held & button as u8 != 0
+    }
+
+    fn pressed(&self, button: PadButton) -> bool {
+        self.down(button) && self.previous & button as u8 == 0
+    }
+}
+
+// Directions and buttons used to drive menus
+#[derive(Clone, Copy)]
+enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+}
+
+// Keyboard and gamepad state behind the player's bindings
+struct Input {
+    bindings: KeyBindings,
+    gamepad: Gamepad,
+}
+
+impl Input {
+    fn new(bindings: KeyBindings) -> Self {
+        Input {
+            bindings,
+            gamepad: Gamepad::new(),
+        }
+    }
+
+    fn poll(&mut self) {
+        self.gamepad.poll();
+    }
+
+    fn down(&self, action: Action) -> bool {
+        is_key_down(self.bindings.key(action)) ||
+        action.arrow_key().is_some_and(is_key_down) ||
+        self.gamepad.down(action.pad_button())
+    }
+
+    fn pressed(&self, action: Action) -> bool {
+        is_key_pressed(self.bindings.key(action)) ||
+        action.arrow_key().is_some_and(is_key_pressed) ||
+        self.gamepad.pressed(action.pad_button())
+    }
+
+    fn pause_pressed(&self) -> bool {
+        is_key_pressed(KeyCode::Escape) || self.gamepad.pressed(PadButton::Start)
+    }
+
+    fn menu_pressed(&self, input: MenuInput) -> bool {
+        let (keys, button): (&[KeyCode], PadButton) = match input {
+            MenuInput::Up => (&[KeyCode::Up, KeyCode::W], PadButton::Up),
+            MenuInput::Down => (&[KeyCode::Down, KeyCode::S], PadButton::Down),
+            MenuInput::Left => (&[KeyCode::Left, KeyCode::A], PadButton::Left),
+            MenuInput::Right => (&[KeyCode::Right, KeyCode::D], PadButton::Right),
+            MenuInput::Confirm => (&[KeyCode::Enter, KeyCode::Space], PadButton::South),
+            MenuInput::Back => (&[KeyCode::Escape, KeyCode::Backspace], PadButton::East),
+        };
+        keys.iter().any(|&key| is_key_pressed(key)) || self.gamepad.pressed(button)
+    }
+}
+
+const SAVE_PATH: &str = "slack_game_save.txt";
+// Every death, one JSON object per line, for the --heatmap tool
+const DEATH_LOG_PATH: &str = "slack_game_deaths.jsonl";
+const HIGH_SCORE_COUNT: usize = 5;
+const STARTING_LIVES: i32 = 3;
+// Death sequence: hurt pose, then a hop before falling off screen
+const DEATH_FREEZE_TIME: f32 = 0.5;
+const DEATH_HOP_SPEED: f32 = -450.0;
+// Shortest time from death to the lives screen, so pit deaths still play out
+const DEATH_MIN_TIME: f32 = 1.5;
+// Seconds the lives remaining screen shows before the level resumes
+const LIVES_SCREEN_TIME: f32 = 2.0;
+const CONTINUES_PER_RUN: i32 = 3;
+// Seconds to accept a continue before the game is over
+const CONTINUE_TIME: f32 = 10.0;
+const LEVEL_COUNT: usize = 3;
+// Frame gaps longer than this (seconds) pause the game
+const SUSPEND_TIME: f32 = 0.25;
+
+struct Settings {
+    music_volume: u8, // 0-10
+    sound_volume: u8, // 0-10
+    fullscreen: bool,
+    integer_scaling: bool,
+    bindings: KeyBindings,
+    game_speed: u8, // Percent, 50-100, slows the game down for accessibility
+    particles: bool,
+    popups: bool,
+}
+
+// Where Continue picks up: the start of the last level reached
+#[derive(Clone, Copy)]
+struct Progress {
+    level: usize,
+    score: i32,
+    lives: i32,
+    continues_used: i32, // Carried over so leaving for the menu doesn't refill them
+}
+
+// Everything kept between runs, stored as key=value lines
+struct SaveData {
+    unlocked_levels: usize,
+    progress: Option<Progress>,
+    high_scores: Vec<i32>, // Best first
+    settings: Settings,
+    achievements: Achievements,
+    stats: LifetimeStats,
+}
+
+impl Settings {
+    fn standard() -> Self {
+        Settings {
+            music_volume: 8,
+            sound_volume: 8,
+            fullscreen: false,
+            integer_scaling: false,
+            bindings: KeyBindings::standard(),
+            game_speed: 100,
+            particles: true,
+            popups: true,
+        }
+    }
+
+    fn scale_mode(&self) -> ScaleMode {
+        if self.integer_scaling { ScaleMode::Integer } else { ScaleMode::Fit }
+    }
+}
+
+impl Progress {
+    // Start of a new run
+    fn new(level: usize) -> Self {
+        Progress { level, score: 0, lives: STARTING_LIVES, continues_used: 0 }
+    }
+}
+
+impl SaveData {
+    fn new() -> Self {
+        SaveData {
+            unlocked_levels: 1,
+            progress: None,
+            high_scores: Vec::new(),
+            settings: Settings::standard(),
+            achievements: Achievements::new(),
+            stats: LifetimeStats::new(),
+        }
+    }
+
+    fn load() -> Self {
+        match std::fs::read_to_string(SAVE_PATH) {
+            Ok(text) => SaveData::parse(&text),
+            Err(_) => SaveData::new(),
+        }
+    }
+
+    // Unknown keys and bad values are skipped so old saves keep loading
+    fn parse(text: &str) -> Self {
+        let mut save = SaveData::new();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let settings = &mut save.settings;
+            match key.trim() {
+                "unlocked_levels" => save.unlocked_levels = value.parse().unwrap_or(1).max(1),
+                "continue" => {
+                    let parts: Vec<&str> = value.split(',').collect();
+                    // Saves from before continues were counted have no fourth field
+                    let continues_used = parts.get(3).and_then(|used| used.parse().ok()).unwrap_or(0);
+                    if let [level, score, lives, ..] = parts[..] &&
+                       let (Ok(level), Ok(score), Ok(lives)) = (level.parse(), score.parse(), lives.parse()) {
+                        save.progress = Some(Progress { level, score, lives, continues_used });
+                    }
+                }
+                "high_scores" => {
+                    save.high_scores = value.split(',').filter_map(|score| score.parse().ok()).collect();
+                }
+                "music_volume" => settings.music_volume = value.parse().unwrap_or(8).min(10),
+                "sound_volume" => settings.sound_volume = value.parse().unwrap_or(8).min(10),
+                "fullscreen" => settings.fullscreen = value == "true",
+                "integer_scaling" => settings.integer_scaling = value == "true",
+                "game_speed" => settings.game_speed = value.parse().unwrap_or(100).clamp(50, 100),
+                "particles" => settings.particles = value == "true",
+                "popups" => settings.popups = value == "true",
+                "achievements" => {
+                    for id in value.split(',') {
+                        if let Some(index) = ACHIEVEMENTS.iter().position(|achievement| achievement.id == id) {
+                            save.achievements.unlocked[index] = true;
+                        }
+                    }
+                }
+                "jumps" => save.stats.jumps = value.parse().unwrap_or(0),
+                "deaths" => {
+                    for (deaths, count) in save.stats.deaths.iter_mut().zip(value.split(',')) {
+                        *deaths = count.parse().unwrap_or(0);
+                    }
+                }
+                "stomps" => save.stats.stomps = value.parse().unwrap_or(0),
+                "coins" => save.stats.coins = value.parse().unwrap_or(0),
+                "distance" => save.stats.distance = value.parse().unwrap_or(0.0),
+                "play_time" => save.stats.play_time = value.parse().unwrap_or(0.0),
+                // Levels never finished are left empty
+                "best_times" => {
+                    for (best, time) in save.stats.best_times.iter_mut().zip(value.split(',')) {
+                        *best = time.parse().ok();
+                    }
+                }
+                "attempts" => {
+                    for (attempts, count) in save.stats.attempts.iter_mut().zip(value.split(',')) {
+                        *attempts = count.parse().unwrap_or(0);
+                    }
+                }
+                binding => {
+                    let action = Action::ALL.iter().find(|action| format!("bind_{:?}", action) == binding);
+                    if let Some(&action) = action && let Some(key) = parse_key(value) {
+                        settings.bindings.set(action, key);
+                    }
+                }
+            }
+        }
+        save
+    }
+
+    fn save(&self) {
+        if let Err(error) = std::fs::write(SAVE_PATH, self.to_text()) {
+            println!("Could not write {}: {}", SAVE_PATH, error);
+        }
+    }
+
+    // The save file's contents, read back by parse
+    fn to_text(&self) -> String {
+        let settings = &self.settings;
+        let stats = &self.stats;
+        let join = |numbers: &[i32]| numbers.iter().map(|number| number.to_string()).collect::<Vec<_>>().join(",");
+        let mut lines = vec![
+            format!("unlocked_levels={}", self.unlocked_levels),
+            format!("high_scores={}", join(&self.high_scores)),
+            format!("music_volume={}", settings.music_volume),
+            format!("sound_volume={}", settings.sound_volume),
+            format!("fullscreen={}", settings.fullscreen),
+            format!("integer_scaling={}", settings.integer_scaling),
+            format!("game_speed={}", settings.game_speed),
+            format!("particles={}", settings.particles),
+            format!("popups={}", settings.popups),
+            format!("achievements={}", ACHIEVEMENTS.iter()
+                .zip(self.achievements.unlocked)
+                .filter(|(_, unlocked)| *unlocked)
+                .map(|(achievement, _)| achievement.id)
+                .collect::<Vec<_>>()
+                .join(",")),
+            format!("jumps={}", stats.jumps),
+            format!("deaths={}", join(&stats.deaths)),
+            format!("stomps={}", stats.stomps),
+            format!("coins={}", stats.coins),
+            format!("distance={}", stats.distance),
+            format!("play_time={}", stats.play_time),
+            format!("best_times={}", stats.best_times.iter()
+                .map(|time| time.map_or(String::new(), |time| time.to_string()))
+                .collect::<Vec<_>>()
+                .join(",")),
+            format!("attempts={}", join(&stats.attempts)),
+        ];
+        if let Some(progress) = self.progress {
+            lines.push(format!(
+                "continue={},{},{},{}",
+                progress.level, progress.score, progress.lives, progress.continues_used
+            ));
+        }
+        for action in Action::ALL {
+            lines.push(format!("bind_{:?}={}", action, key_name(settings.bindings.key(action))));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    fn record_score(&mut self, score: i32) {
+        if score <= 0 {
+            return;
+        }
+        let rank = self.high_scores.iter().position(|&best| score > best).unwrap_or(self.high_scores.len());
+        self.high_scores.insert(rank, score);
+        self.high_scores.truncate(HIGH_SCORE_COUNT);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MenuScreen {
+    Main,
+    Pause,
+    ConfirmQuit,
+    LevelSelect,
+    Options,
+    Audio,
+    Video,
+    Controls,
+    Accessibility,
+    HighScores,
+    Achievements,
+    Statistics,
+}
+
+// What choosing or changing a menu item does
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    NewGame,
+    Continue,
+    StartLevel(usize),
+    Open(MenuScreen),
+    Quit,
+    Resume,
+    RestartLevel,
+    QuitToMenu,
+    Back,
+    MusicVolume,
+    SoundVolume,
+    Fullscreen,
+    IntegerScaling,
+    Bind(Action),
+    ResetBindings,
+    GameSpeed,
+    Particles,
+    Popups,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Widget {
+    Button,
+    Slider { value: u8, max: u8 },
+    Toggle(bool),
+    Binding(KeyCode),
+}
+
+struct MenuItem {
+    text: String,
+    widget: Widget,
+    command: Option<Command>, // None for plain labels
+    enabled: bool,
+}
+
+enum MenuEvent {
+    Pressed(Command),
+    Changed(Command, Widget),
+    Back,
+}
+
+// Vertical list of widgets navigated with the menu inputs
+struct Menu {
+    title: &'static str,
+    items: Vec<MenuItem>,
+    selected: usize,
+    capturing: bool, // Waiting for a key to bind
+}
+
+impl MenuScreen {
+    fn build(self, game: &Game) -> Menu {
+        let settings = &game.save.settings;
+        match self {
+            MenuScreen::Main => Menu::new("SLACK GAME", vec![
+                MenuItem::button("New Game", Command::NewGame),
+                MenuItem::button("Continue", Command::Continue).enabled(game.save.progress.is_some()),
+                MenuItem::button("Level Select", Command::Open(MenuScreen::LevelSelect)),
+                MenuItem::button("Options", Command::Open(MenuScreen::Options)),
+                MenuItem::button("High Scores", Command::Open(MenuScreen::HighScores)),
+                MenuItem::button("Achievements", Command::Open(MenuScreen::Achievements)),
+                MenuItem::button("Statistics", Command::Open(MenuScreen::Statistics)),
+                MenuItem::button("Quit", Command::Quit),
+            ]),
+            MenuScreen::Pause => Menu::new("PAUSED", vec![
+                MenuItem::button("Resume", Command::Resume),
+                MenuItem::button("Restart Level", Command::RestartLevel),
+                MenuItem::button("Options", Command::Open(MenuScreen::Options)),
+                MenuItem::button("Quit to Main Menu", Command::Open(MenuScreen::ConfirmQuit)),
+            ]),
+            MenuScreen::ConfirmQuit => Menu::new("QUIT TO MAIN MENU?", vec![
+                MenuItem::label("Progress in this level will be lost"),
+                MenuItem::button("Keep Playing", Command::Back),
+                MenuItem::button("Quit", Command::QuitToMenu),
+            ]),
+            MenuScreen::LevelSelect => {
+                let unlocked = game.save.unlocked_levels.min(LEVEL_COUNT);
+                let mut items: Vec<MenuItem> = (0..unlocked)
+                    .map(|level| MenuItem::button(&format!("Level {}", level + 1), Command::StartLevel(level)))
+                    .collect();
+                items.push(MenuItem::button("Back", Command::Back));
+                Menu::new("LEVEL SELECT", items)
+            }
+            MenuScreen::Options => Menu::new("OPTIONS", vec![
+                MenuItem::button("Audio", Command::Open(MenuScreen::Audio)),
+                MenuItem::button("Video", Command::Open(MenuScreen::Video)),
+                MenuItem::button("Controls", Command::Open(MenuScreen::Controls)),
+                MenuItem::button("Accessibility", Command::Open(MenuScreen::Accessibility)),
+                MenuItem::button("Back", Command::Back),
+            ]),
+            MenuScreen::Audio => Menu::new("AUDIO", vec![
+                MenuItem::new("Music", Widget::Slider { value: settings.music_volume, max: 10 }, Command::MusicVolume),
+                MenuItem::new("Sound", Widget::Slider { value: settings.sound_volume, max: 10 }, Command::SoundVolume),
+                MenuItem::button("Back", Command::Back),
+            ]),
+            MenuScreen::Video => Menu::new("VIDEO", vec![
+                MenuItem::new("Fullscreen", Widget::Toggle(settings.fullscreen), Command::Fullscreen),
+                MenuItem::new("Integer Scale", Widget::Toggle(settings.integer_scaling), Command::IntegerScaling),
+                MenuItem::button("Back", Command::Back),
+            ]),
+            MenuScreen::Controls => {
+                let mut items: Vec<MenuItem> = Action::ALL.iter()
+                    .map(|&action| {
+                        MenuItem::new(action.label(), Widget::Binding(settings.bindings.key(action)), Command::Bind(action))
+                    })
+                    .collect();
+                items.push(MenuItem::button("Reset Defaults", Command::ResetBindings));
+                items.push(MenuItem::button("Back", Command::Back));
+                Menu::new("CONTROLS", items)
+            }
+            MenuScreen::Accessibility => Menu::new("ACCESSIBILITY", vec![
+                // Slider steps of 10% between half and full speed
+                MenuItem::new(
+                    "Game Speed",
+                    Widget::Slider { value: (settings.game_speed - 50) / 10, max: 5 },
+                    Command::GameSpeed,
+                ),
+                MenuItem::new("Particles", Widget::Toggle(settings.particles), Command::Particles),
+                MenuItem::new("Score Popups", Widget::Toggle(settings.popups), Command::Popups),
+                MenuItem::button("Back", Command::Back),
+            ]),
+            MenuScreen::HighScores => {
+                let mut items: Vec<MenuItem> = game.save.high_scores.iter()
+                    .enumerate()
+                    .map(|(rank, score)| MenuItem::label(&format!("{}.  {:>7}", rank + 1, score)))
+                    .collect();
+                if items.is_empty() {
+                    items.push(MenuItem::label("No scores yet"));
+                }
+                items.push(MenuItem::button("Back", Command::Back));
+                Menu::new("HIGH SCORES", items)
+            }
+            MenuScreen::Achievements => {
+                // Locked achievements are greyed out
+                let achievements = &game.save.achievements;
+                let mut items: Vec<MenuItem> = (0..ACHIEVEMENTS.len())
+                    .map(|index| {
+                        MenuItem::label(&achievements.describe(index, &game.save.stats)).enabled(achievements.unlocked[index])
+                    })
+                    .collect();
+                items.push(MenuItem::button("Back", Command::Back));
+                Menu::new("ACHIEVEMENTS", items)
+            }
+            MenuScreen::Statistics => {
+                let stats = &game.save.stats;
+                let deaths: Vec<String> = DeathCause::ALL.iter()
+                    .map(|&cause| format!("{} {}", cause.label(), stats.deaths[cause as usize]))
+                    .collect();
+                let mut items = vec![
+                    MenuItem::label(&format!("Jumps: {}   Stomps: {}", stats.jumps, stats.stomps)),
+                    // A 32 pixel tile counts as a metre
+                    MenuItem::label(&format!("Coins: {}   Distance: {} m", stats.coins, (stats.distance / 32.0) as i32)),
+                    MenuItem::label(&format!("Play time: {}", format_time(stats.play_time))),
+                    MenuItem::label(&format!("Deaths: {} ({})", stats.total_deaths(), deaths.join(", "))),
+                ];
+                for level in 0..LEVEL_COUNT {
+                    let best = stats.best_times[level].map_or("--".to_string(), format_time);
+                    let line = format!("Level {}: best {}, {} attempts", level + 1, best, stats.attempts[level]);
+                    items.push(MenuItem::label(&line));
+                }
+                items.push(MenuItem::button("Back", Command::Back));
+                Menu::new("STATISTICS", items)
+            }
+        }
+    }
+}
+
+impl MenuItem {
+    fn new(text: &str, widget: Widget, command: Command) -> Self {
+        MenuItem {
+            text: text.to_string(),
+            widget,
+            command: Some(command),
+            enabled: true,
+        }
+    }
+
+    fn button(text: &str, command: Command) -> Self {
+        MenuItem::new(text, Widget::Button, command)
+    }
+
+    fn label(text: &str) -> Self {
+        MenuItem {
+            text: text.to_string(),
+            widget: Widget::Button,
+            command: None,
+            enabled: true,
+        }
+    }
+
+    fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    fn selectable(&self) -> bool {
+        self.command.is_some() && self.enabled
+    }
+}
+
+impl Menu {
+    fn new(title: &'static str, items: Vec<MenuItem>) -> Self {
+        let selected = items.iter().position(MenuItem::selectable).unwrap_or(0);
+        Menu {
+            title,
+            items,
+            selected,
+            capturing: false,
+        }
+    }
+
+    // Moves the cursor to the next selectable item, wrapping at the ends
+    fn step(&mut self, forward: bool) {
+        let count = self.items.len();
+        for offset in 1..count {
+            let index = if forward {
+                (self.selected + offset) % count
+            } else {
+                (self.selected + count - offset) % count
+            };
+            if self.items[index].selectable() {
+                self.selected = index;
+                return;
+            }
+        }
+    }
+
+    fn update(&mut self, input: &Input) -> Option<MenuEvent> {
+        if self.capturing {
+            let item = &mut self.items[self.selected];
+            if is_key_pressed(KeyCode::Escape) {
+                self.capturing = false;
+            } else if let Some(key) = get_last_key_pressed() &&
+                      BINDABLE_KEYS.contains(&key) {
+                self.capturing = false;
+                item.widget = Widget::Binding(key);
+                return item.command.map(|command| MenuEvent::Changed(command, item.widget));
+            }
+            return None;
+        }
+
+        if input.menu_pressed(MenuInput::Back) {
+            return Some(MenuEvent::Back);
+        }
+        if input.menu_pressed(MenuInput::Up) {
+            self.step(false);
+        }
+        if input.menu_pressed(MenuInput::Down) {
+            self.step(true);
+        }
+
+        let item = self.items.get_mut(self.selected)?;
+        if !item.selectable() {
+            return None;
+        }
+        let command = item.command?;
+        let confirm = input.menu_pressed(MenuInput::Confirm);
+        let left = input.menu_pressed(MenuInput::Left);
+        let right = input.menu_pressed(MenuInput::Right);
+        match &mut item.widget {
+            Widget::Button => {
+                if confirm {
+                    return Some(MenuEvent::Pressed(command));
+                }
+            }
+            Widget::Slider { value, max } => {
+                let previous = *value;
+                if left {
+                    *value = value.saturating_sub(1);
+                }
+                if right {
+                    *value = (*value + 1).min(*max);
+                }
+                if *value != previous {
+                    return Some(MenuEvent::Changed(command, item.widget));
+                }
+            }
+            Widget::Toggle(on) => {
+                if confirm || left || right {
+                    *on = !*on;
+                    return Some(MenuEvent::Changed(command, item.widget));
+                }
+            }
+            Widget::Binding(_) => {
+                if confirm {
+                    self.capturing = true;
+                }
+            }
+        }
+        None
+    }
+
+    fn draw(&self, viewport: &Viewport) {
+        viewport.text(self.title, Anchor::Center, 0.0, -180.0, 50.0, GOLD);
+        for (index, item) in self.items.iter().enumerate() {
+            let y = -110.0 + index as f32 * 40.0;
+            let color = if !item.enabled {
+                GRAY
+            } else if index == self.selected && item.selectable() {
+                YELLOW
+            } else {
+                WHITE
+            };
+
+            // Buttons and labels are centred, other widgets put their value on the right
+            let value = match item.widget {
+                Widget::Button => {
+                    viewport.text(&item.text, Anchor::Center, 0.0, y, 30.0, color);
+                    continue;
+                }
+                Widget::Slider { value, max } => {
+                    let bar = viewport.anchor(Anchor::Center, 40.0, y - 14.0);
+                    let width = 160.0 * viewport.scale;
+                    let height = 12.0 * viewport.scale;
+                    draw_rectangle(bar.x, bar.y, width, height, DARKGRAY);
+                    draw_rectangle(bar.x, bar.y, width * value as f32 / max as f32, height, color);
+                    String::new()
+                }
+                Widget::Toggle(on) => if on { "On".to_string() } else { "Off".to_string() },
+                Widget::Binding(key) => {
+                    if self.capturing && index == self.selected {
+                        "Press a key...".to_string()
+                    } else {
+                        key_name(key)
+                    }
+                }
+            };
+            viewport.text(&item.text, Anchor::Center, -120.0, y, 30.0, color);
+            viewport.text(&value, Anchor::Center, 120.0, y, 30.0, color);
+        }
+        let hint = if self.capturing {
+            "Press the new key, ESC to cancel"
+        } else {
+            "Arrows: Move   Enter: Select   ESC: Back"
+        };
+        viewport.text(hint, Anchor::Bottom, 0.0, -20.0, 20.0, GRAY);
+    }
+}
+
+// What only the player has; their position, movement and sprite are
+// components of the PLAYER entity in the current room's world
+struct Player {
+    score: i32,
+    speed_boost: f32,
+    jump_boost: f32,
+    lives: i32,
+    facing_right: bool,
+    stomp_chain: usize,    // Enemies stomped since last touching the ground
+    coins: i32,
+    dying: Option<f32>, // Seconds since dying, None while alive
+}
+
+// Where the player is and which way they're headed, for the camera and warps
+#[derive(Clone, Copy)]
+struct PlayerView {
+    rect: Rect,
+    facing_right: bool,
+    on_ground: bool,
+}
+
+// Uniform grid bucketing fixed rectangles by the cells they overlap, so
+// collision checks only visit what's nearby instead of everything in the room
+#[derive(Default)]
+struct SpatialGrid {
+    origin: Vec2,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<GridEntry>>, // Row by row
+}
+
+#[derive(Clone, Copy)]
+struct GridEntry {
+    index: usize,  // Into the slice the grid was built from
+    column: usize, // Top-left cell the rectangle covers, so queries report it once
+    row: usize,
+}
+
+// A room's solid geometry. Systems borrow it immutably alongside the
+// world, and it only changes when a brick breaks
+struct Terrain {
+    platforms: Vec<Option<Platform>>, // Broken bricks leave an empty slot, so indices stay valid
+    grid: SpatialGrid,
+}
+
+// Every entity in a room, one column per component, borrowed mutably while
+// the terrain is read. Despawning empties the entity's slot in every column
+struct World {
+    transforms: Vec<Option<Transform>>,
+    velocities: Vec<Option<Velocity>>,
+    colliders: Vec<Option<Collider>>,
+    bodies: Vec<Option<Body>>,
+    sprites: Vec<Option<Sprite>>,
+    pickups: Vec<Option<Pickup>>,
+    hostiles: Vec<Option<Hostile>>,
+    patrols: Vec<Option<Patrol>>,
+    corpses: Vec<Option<Corpse>>,
+    hostile_entities: Vec<Entity>, // Everything with a Hostile, so damage checks skip the rest
+    pickup_grid: SpatialGrid, // Pickups never move, only get collected
+    pickup_entities: Vec<Entity>, // Grid index to entity
+    coins: usize, // Coin pickups spawned, collected or not
+}
+
+struct Room {
+    terrain: Terrain,
+    world: World,
+    warps: Vec<Warp>,
+    theme: Option<Theme>, // Overrides the level's theme when set
+    music: Music,
+    bounds: Rect, // Area the camera is allowed to show
+    camera_zones: Vec<CameraZone>,
+}
+
+struct Level {
+    // Room 0 is the main room where the level starts and the goal lives
+    rooms: Vec<Room>,
+    current_room: usize,
+    theme: Theme,
+    camera: CameraSettings,
+    goal_x: f32,
+    goal_y: f32,
+    par_time: f32, // Seconds to beat for the under par achievement
+}
+
+// Totals for the current run, shown on the game over screen
+#[derive(Default)]
+struct RunStats {
+    time: f32,
+    levels_cleared: i32,
+    coins: i32,
+    stomps: i32,
+    deaths: i32,
+    continues_used: i32,
+}
+
+// What cost the player a life
+#[derive(Clone, Copy, PartialEq)]
+enum DeathCause {
+    Enemy,
+    Fall,
+    Crushed, // Pushed into a wall by a scrolling screen
+}
+
+// Things that happened during a tick, queued for the systems that react to them
+enum GameEvent {
+    LevelStarted { level: usize, music: Music }, // Played from the top even if it was already on
+    Ticked { dt: f32 },        // Real time passed in play, death sequences and warps included
+    Moved { distance: f32 },  // Horizontal distance the player covered this tick
+    Jumped,
+    CoinCollected { at: Rect },
+    EnemyStomped { at: Rect, chain: usize }, // Stomps since the player last landed
+    PowerUpCollected { at: Rect, power_type: PowerUpType },
+    BrickSmashed { at: Rect },
+    ExtraLife { at: Rect, coins: bool },     // From the hundredth coin rather than a stomp chain
+    Warped,
+    PlayerHurt,
+    PlayerDied { cause: DeathCause, x: f32, y: f32 }, // Centre of the player's body where they were hit or fell
+    LevelCompleted { level: usize, time: f32, par_time: f32, coins: usize, total_coins: usize },
+}
+
+// Condition that unlocks an achievement, checked against each event
+enum AchievementRule {
+    Stomps(i32),                   // Total across every run
+    NoCoins { level: usize },      // Finish the level without collecting a coin
+    NoDamage { level: usize },     // Finish the level without getting hurt
+    AllCoins,                      // Collect every coin in any level
+    UnderPar,                      // Finish any level faster than its par time
+}
+
+struct AchievementDef {
+    id: &'static str, // Saved name, never change once released
+    name: &'static str,
+    description: &'static str,
+    rule: AchievementRule,
+}
+
+const ACHIEVEMENTS: [AchievementDef; 5] = [
+    AchievementDef {
+        id: "stomp_50",
+        name: "Goomba Crusher",
+        description: "Stomp 50 enemies",
+        rule: AchievementRule::Stomps(50),
+    },
+    AchievementDef {
+        id: "no_coins_1_1",
+        name: "Penny Pincher",
+        description: "Finish 1-1 without collecting a coin",
+        rule: AchievementRule::NoCoins { level: 0 },
+    },
+    AchievementDef {
+        id: "no_damage_1_3",
+        name: "Untouchable",
+        description: "Complete 1-3 without taking damage",
+        rule: AchievementRule::NoDamage { level: 2 },
+    },
+    AchievementDef {
+        id: "all_coins",
+        name: "Coin Collector",
+        description: "Collect every coin in a level",
+        rule: AchievementRule::AllCoins,
+    },
+    AchievementDef {
+        id: "under_par",
+        name: "Speedrunner",
+        description: "Finish a level under par time",
+        rule: AchievementRule::UnderPar,
+    },
+];
+
+// Unlocks and the per-level state the rules need; saved with the rest of the save data
+struct Achievements {
+    unlocked: [bool; ACHIEVEMENTS.len()],
+    hurt_this_level: bool,
+}
+
+// Totals across every session, saved with the rest of the save data
+struct LifetimeStats {
+    jumps: i32,
+    deaths: [i32; DeathCause::ALL.len()], // Indexed by DeathCause
+    stomps: i32,
+    coins: i32,
+    distance: f32, // Pixels
+    play_time: f32,
+    best_times: [Option<f32>; LEVEL_COUNT],
+    attempts: [i32; LEVEL_COUNT],
+}
+
+// Seconds each unlock message stays on screen
+const TOAST_TIME: f32 = 3.0;
+
+// Unlock messages shown one after another at the top of the screen
+struct Toasts {
+    queue: Vec<(&'static str, f32)>, // Achievement name and time shown so far
+}
+
+// Changes to the level made while a transition covers the screen
+enum LevelAction {
+    Warp { room: usize, x: f32, y: f32 },
+}
+
+struct Game {
+    player: Player,
+    camera: Camera,
+    theme_art: ThemeArt,
+    sprites: Sprites,
+    particles: Particles,
+    popups: Popups,
+    audio: Audio,
+    canvas: Canvas,
+    input: Input,
+    save: SaveData,
+    fullscreen: bool, // Last value handed to the window
+    level_start_score: i32,
+    level_start_coins: i32,
+    level_start_lives: i32,
+    transition: Option<Transition<LevelAction>>,
+    run: RunStats,
+    level_time: f32,
+    events: Vec<GameEvent>, // Queued this tick, handled by dispatch_events
+    spare_events: Vec<GameEvent>, // Swapped with events while dispatching, so neither reallocates
+    toasts: Toasts,
+    levels: Vec<Level>,
+    current_level: usize,
+    level_completed: bool,
+    // Simple audio using frequency synthesis (no external files needed)
+}
+
+impl Player {
+    fn new() -> Self {
+        Player {
+            score: 0,
+            speed_boost: 1.0,
+            jump_boost: 1.0,
+            lives: STARTING_LIVES,
+            facing_right: true,
+            stomp_chain: 0,
+            coins: 0,
+            dying: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.speed_boost = 1.0;
+        self.jump_boost = 1.0;
+        self.facing_right = true;
+        self.stomp_chain = 0;
+        self.dying = None;
+    }
+
+    fn die(&mut self, world: &mut World) {
+        self.dying = Some(0.0);
+        if let Some(velocity) = &mut world.velocities[PLAYER] {
+            *velocity = Velocity::default();
+        }
+        if let Some(animator) = world.animator_mut(PLAYER) {
+            animator.set_state(AnimState::Hurt);
+        }
+    }
+
+    // Freeze, hop, then fall through everything off the bottom of the screen
+    fn update_dying(&mut self, dt: f32, world: &mut World) {
+        let Some(time) = &mut self.dying else {
+            return;
+        };
+        *time += dt;
+        let (Some(transform), Some(velocity), Some(animator)) = (
+            &mut world.transforms[PLAYER],
+            &mut world.velocities[PLAYER],
+            world.sprites[PLAYER].as_mut().and_then(|sprite| sprite.animator.as_mut()),
+        ) else {
+            return;
+        };
+        if *time >= DEATH_FREEZE_TIME {
+            if animator.state == AnimState::Hurt {
+                animator.set_state(AnimState::Die);
+                velocity.y = DEATH_HOP_SPEED;
+            }
+            velocity.y += 800.0 * dt;
+            transform.y += velocity.y * dt;
+        }
+        animator.update(dt);
+    }
+
+    // Steers the player's entity, returns true if it jumped
+    fn handle_input(&mut self, input: &Input, world: &mut World) -> bool {
+        let speed = 200.0 * self.speed_boost;
+        let jump_force = -500.0 * self.jump_boost;
+        let on_ground = world.on_ground(PLAYER);
+        let Some(velocity) = &mut world.velocities[PLAYER] else {
+            return false;
+        };
+        let mut jumped = false;
+        
+        if input.down(Action::Left) {
+            velocity.x = -speed;
+            self.facing_right = false;
+        }
+        if input.down(Action::Right) {
+            velocity.x = speed;
+            self.facing_right = true;
+        }
+        if (input.pressed(Action::Up) || input.pressed(Action::Jump)) && on_ground {
+            velocity.y = jump_force;
+            jumped = true;
+        }
+        if let Some(animator) = world.animator_mut(PLAYER) {
+            animator.flip_x = !self.facing_right;
+        }
+        jumped
+    }
+
+    // Score, lives and boosts earned from an event, with popups showing them.
+    // Extra lives it leads to are published as events of their own
+    fn award(&mut self, event: &GameEvent, at_player: Rect, popups: &mut Popups, events: &mut Vec<GameEvent>) {
+        match *event {
+            GameEvent::CoinCollected { at } => {
+                self.score += 10;
+                self.coins += 1;
+                popups.spawn(PopupText::Points(10), at.center().x, at.y);
+                // Every hundredth coin is worth an extra life
+                if self.coins % 100 == 0 {
+                    events.push(GameEvent::ExtraLife { at: at_player, coins: true });
+                }
+            }
+            // Chained stomps escalate and end in extra lives
+            GameEvent::EnemyStomped { at, chain } => match STOMP_CHAIN.get(chain) {
+                Some(&points) => {
+                    self.score += points;
+                    popups.spawn(PopupText::Points(points), at.center().x, at.y);
+                }
+                None => events.push(GameEvent::ExtraLife { at, coins: false }),
+            },
+            GameEvent::PowerUpCollected { at, power_type } => {
+                match power_type {
+                    PowerUpType::SpeedBoost => self.speed_boost = 1.5,
+                    PowerUpType::JumpBoost => self.jump_boost = 1.3,
+                }
+                self.score += 50;
+                popups.spawn(PopupText::Points(50), at.center().x, at.y);
+            }
+            GameEvent::BrickSmashed { at } => {
+                self.score += 50;
+                popups.spawn(PopupText::Points(50), at.center().x, at.y);
+            }
+            GameEvent::ExtraLife { at, .. } => {
+                self.lives += 1;
+                popups.spawn(PopupText::OneUp, at.center().x, at.y);
+            }
+            GameEvent::PlayerDied { .. } => self.lives -= 1,
+            GameEvent::LevelCompleted { .. } => self.score += 1000,
+            _ => {}
+        }
+    }
+
+    // Rectangle versions of every player frame, drawn facing right
+    fn draw_procedural(sprite: &str, canvas: SpriteCanvas) {
+        let body_color = if sprite == "player_hurt" { WHITE } else { RED };
+        // Main body
+        canvas.rect(8.0, 4.0, 16.0, 24.0, body_color);
+        // Head
+        canvas.rect(10.0, 0.0, 12.0, 8.0, PINK);
+        // Eyes
+        if sprite == "player_die" {
+            canvas.rect(13.0, 3.0, 3.0, 1.0, BLACK);
+            canvas.rect(19.0, 3.0, 3.0, 1.0, BLACK);
+        } else {
+            canvas.rect(14.0, 2.0, 2.0, 2.0, BLACK);
+            canvas.rect(20.0, 2.0, 2.0, 2.0, BLACK);
+        }
+
+        match sprite {
+            "player_walk_0" => {
+                canvas.rect(6.0, 28.0, 6.0, 4.0, BROWN);
+                canvas.rect(22.0, 30.0, 6.0, 2.0, BROWN);
+            }
+            "player_walk_1" => {
+                canvas.rect(4.0, 30.0, 6.0, 2.0, BROWN);
+                canvas.rect(20.0, 28.0, 6.0, 4.0, BROWN);
+            }
+            "player_run" => {
+                // Mid-stride with both feet off the ground
+                canvas.rect(2.0, 27.0, 6.0, 3.0, BROWN);
+                canvas.rect(24.0, 27.0, 6.0, 3.0, BROWN);
+            }
+            "player_jump" => {
+                // Feet together
+                canvas.rect(12.0, 28.0, 8.0, 4.0, BROWN);
+            }
+            "player_fall" => {
+                // Feet spread for landing
+                canvas.rect(4.0, 28.0, 6.0, 4.0, BROWN);
+                canvas.rect(22.0, 28.0, 6.0, 4.0, BROWN);
+            }
+            _ => {
+                // Feet
+                canvas.rect(6.0, 28.0, 6.0, 4.0, BROWN);
+                canvas.rect(20.0, 28.0, 6.0, 4.0, BROWN);
+            }
+        }
+    }
+}
+
+impl Audio {
+    fn new() -> Self {
+        Audio { music_volume: 0, sound_volume: 0 }
+    }
+
+    fn play(&self, sound: Sound) {
+        if self.sound_volume == 0 {
+            return;
+        }
+        let name = match sound {
+            Sound::Jump => "Jump sound!",
+            Sound::Coin => "Coin collected!",
+            Sound::EnemyDefeat => "Enemy defeated!",
+            Sound::PowerUp => "Power-up collected!",
+            Sound::Hit => "Player hit!",
+            Sound::LevelComplete => "Level complete!",
+            Sound::CoinOneUp => "100 coins jingle!",
+            Sound::OneUp => "1-Up!",
+            Sound::Brick => "Brick smashed!",
+            Sound::Warp => "Warp!",
+        };
+        println!("🔊 {} (volume {}/10)", name, self.sound_volume);
+    }
+
+    fn play_music(&self, music: Music) {
+        if self.music_volume == 0 {
+            return;
+        }
+        let track = match music {
+            Music::Overworld => "Overworld theme",
+            Music::Underground => "Underground theme",
+            Music::Castle => "Castle theme",
+            Music::Sky => "Sky theme",
+            Music::Ice => "Ice cavern theme",
+            Music::Bonus => "Bonus room theme",
+        };
+        println!("🎵 Now playing: {} (volume {}/10)", track, self.music_volume);
+    }
+
+    fn observe(&self, event: &GameEvent) {
+        let sound = match event {
+            GameEvent::LevelStarted { music, .. } => return self.play_music(*music),
+            GameEvent::Jumped => Sound::Jump,
+            GameEvent::CoinCollected { .. } => Sound::Coin,
+            GameEvent::EnemyStomped { .. } => Sound::EnemyDefeat,
+            GameEvent::PowerUpCollected { .. } => Sound::PowerUp,
+            GameEvent::BrickSmashed { .. } => Sound::Brick,
+            GameEvent::ExtraLife { coins: true, .. } => Sound::CoinOneUp,
+            GameEvent::ExtraLife { .. } => Sound::OneUp,
+            GameEvent::Warped => Sound::Warp,
+            GameEvent::PlayerDied { .. } => Sound::Hit,
+            GameEvent::LevelCompleted { .. } => Sound::LevelComplete,
+            GameEvent::Ticked { .. } | GameEvent::Moved { .. } | GameEvent::PlayerHurt => return,
+        };
+        self.play(sound);
+    }
+}
+
+impl Level {
+    fn create(index: usize) -> Self {
+        match index {
+            0 => Level::create_level_1(),
+            1 => Level::create_level_2(),
+            _ => Level::create_level_3(),
+        }
+    }
+
+    fn create_level_1() -> Self {
+        // World 1-1: Basic tutorial level
+        let platforms = vec![
+            Platform::new(0.0, 550.0, 800.0, 50.0),       // Ground
+            Platform::new(200.0, 450.0, 150.0, 20.0),     // First platform
+            Platform::new(500.0, 400.0, 120.0, 20.0),     // Higher platform
+            Platform::new(100.0, 350.0, 100.0, 20.0),     // Side platform
+            Platform::new(650.0, 300.0, 150.0, 20.0),     // High platform
+            Platform::new(900.0, 500.0, 200.0, 20.0),     // Landing area
+            Platform::new(1200.0, 400.0, 150.0, 20.0),    // Challenge jump
+            Platform::new(1500.0, 350.0, 100.0, 20.0),    // Near goal
+            Platform::new(1700.0, 500.0, 200.0, 50.0),    // Goal platform
+            Platform::pipe(720.0, 502.0, 48.0),           // Pipe down to bonus room
+            Platform::brick(400.0, 430.0),                // Breakable bricks
+            Platform::brick(432.0, 430.0),
+            Platform::brick(464.0, 430.0),
+            Platform::pipe(1710.0, 452.0, 48.0),          // Bonus room exit
+        ];
+        
+        let enemies = vec![
+            Bundle::enemy(250.0, 420.0),
+            Bundle::enemy(550.0, 370.0),
+            Bundle::enemy(950.0, 470.0),
+            Bundle::enemy(1250.0, 370.0),
+        ];
+        
+        let coins = vec![
+            Bundle::coin(150.0, 520.0),
+            Bundle::coin(275.0, 430.0),
+            Bundle::coin(325.0, 430.0),
+            Bundle::coin(575.0, 380.0),
+            Bundle::coin(700.0, 280.0),
+            Bundle::coin(1000.0, 480.0),
+            Bundle::coin(1300.0, 380.0),
+            Bundle::coin(1550.0, 330.0),
+        ];
+        
+        let powerups = vec![
+            Bundle::powerup(275.0, 420.0, PowerUpType::SpeedBoost),
+            Bundle::powerup(750.0, 280.0, PowerUpType::JumpBoost),
+            Bundle::powerup(1000.0, 470.0, PowerUpType::SpeedBoost),
+        ];
+
+        let warps = vec![
+            Warp::pipe(720.0, 502.0, 1, 100.0, 300.0),
+        ];
+
+        let main_room = Room {
+            terrain: Terrain::new(platforms),
+            world: World::new(enemies.into_iter().chain(coins).chain(powerups)),
+            warps,
+            theme: None,
+            music: Music::Overworld,
+            bounds: Rect::new(0.0, -200.0, 1900.0, 800.0),
+            camera_zones: Vec::new(),
+        };
+
+        // Hidden bonus room full of coins, exits near the goal
+        let mut bonus_coins = Vec::new();
+        for row in 0..3 {
+            for column in 0..8 {
+                bonus_coins.push(Bundle::coin(220.0 + column as f32 * 48.0, 380.0 + row as f32 * 40.0));
+            }
+        }
+
+        let bonus_platforms = vec![
+            Platform::new(0.0, 550.0, 800.0, 50.0),   // Floor
+            Platform::new(0.0, 0.0, 20.0, 550.0),     // Left wall
+            Platform::new(780.0, 0.0, 20.0, 550.0),   // Right wall
+            Platform::pipe(680.0, 502.0, 48.0),       // Exit pipe
+        ];
+
+        let bonus_room = Room {
+            terrain: Terrain::new(bonus_platforms),
+            world: World::new(bonus_coins),
+            warps: vec![
+                Warp::pipe(680.0, 502.0, 0, 1718.0, 420.0),
+            ],
+            theme: Some(Theme::Underground),
+            music: Music::Bonus,
+            bounds: Rect::new(0.0, 0.0, 800.0, 600.0),
+            camera_zones: Vec::new(),
+        };
+
+        Level {
+            rooms: vec![main_room, bonus_room],
+            current_room: 0,
+            theme: Theme::Overworld,
+            camera: CameraSettings::standard(),
+            goal_x: 1800.0,
+            goal_y: 450.0,
+            par_time: 30.0,
+        }
+    }
+
+    fn create_level_2() -> Self {
+        // World 1-2: Underground level with more enemies
+        let platforms = vec![
+            Platform::new(0.0, 550.0, 300.0, 50.0),       // Start area
+            Platform::new(400.0, 500.0, 100.0, 20.0),     // First gap
+            Platform::new(600.0, 450.0, 100.0, 20.0),     // Rising platforms
+            Platform::new(800.0, 400.0, 100.0, 20.0),
+            Platform::new(1000.0, 350.0, 100.0, 20.0),
+            Platform::new(1200.0, 300.0, 150.0, 20.0),    // High platform
+            Platform::new(1450.0, 400.0, 100.0, 20.0),    // Drop down
+            Platform::new(1650.0, 500.0, 100.0, 20.0),
+            Platform::new(1850.0, 450.0, 100.0, 20.0),
+            Platform::new(2050.0, 400.0, 200.0, 50.0),    // Goal area
+        ];
+        
+        let enemies = vec![
+            Bundle::enemy(450.0, 470.0),
+            Bundle::enemy(650.0, 420.0),
+            Bundle::enemy(850.0, 370.0),
+            Bundle::enemy(1050.0, 320.0),
+            Bundle::enemy(1300.0, 270.0),
+            Bundle::enemy(1500.0, 370.0),
+            Bundle::enemy(1700.0, 470.0),
+        ];
+        
+        let coins = vec![
+            Bundle::coin(250.0, 520.0),
+            Bundle::coin(450.0, 480.0),
+            Bundle::coin(650.0, 430.0),
+            Bundle::coin(850.0, 380.0),
+            Bundle::coin(1050.0, 330.0),
+            Bundle::coin(1325.0, 280.0),
+            Bundle::coin(1500.0, 380.0),
+            Bundle::coin(1700.0, 480.0),
+            Bundle::coin(1900.0, 430.0),
+            Bundle::coin(2100.0, 380.0),
+        ];
+        
+        let powerups = vec![
+            Bundle::powerup(425.0, 470.0, PowerUpType::JumpBoost),
+            Bundle::powerup(1275.0, 270.0, PowerUpType::SpeedBoost),
+            Bundle::powerup(1875.0, 420.0, PowerUpType::JumpBoost),
+        ];
+
+        let warps = vec![
+            Warp::door(180.0, 502.0, 1, 60.0, 518.0),
+        ];
+
+        let main_room = Room {
+            terrain: Terrain::new(platforms),
+            world: World::new(enemies.into_iter().chain(coins).chain(powerups)),
+            warps,
+            theme: None,
+            music: Music::Underground,
+            bounds: Rect::new(0.0, -200.0, 2250.0, 800.0),
+            // The rising platforms scroll by on their own
+            camera_zones: vec![
+                CameraZone {
+                    area: Rect::new(400.0, -200.0, 1250.0, 800.0),
+                    mode: CameraMode::AutoScroll { speed: 60.0 },
+                },
+            ],
+        };
+
+        // Frozen cavern behind the door at the start
+        let cavern_platforms = vec![
+            Platform::new(0.0, 550.0, 900.0, 50.0),   // Floor
+            Platform::new(0.0, 0.0, 20.0, 550.0),     // Left wall
+            Platform::new(880.0, 0.0, 20.0, 550.0),   // Right wall
+            Platform::new(250.0, 460.0, 120.0, 20.0),
+            Platform::new(450.0, 380.0, 120.0, 20.0),
+            Platform::new(650.0, 300.0, 120.0, 20.0),
+            Platform::brick(120.0, 430.0),
+            Platform::brick(152.0, 430.0),
+        ];
+        let cavern_enemies = vec![
+            Bundle::enemy(500.0, 520.0),
+        ];
+        let cavern_coins = vec![
+            Bundle::coin(290.0, 440.0),
+            Bundle::coin(330.0, 440.0),
+            Bundle::coin(490.0, 360.0),
+            Bundle::coin(530.0, 360.0),
+            Bundle::coin(690.0, 280.0),
+            Bundle::coin(730.0, 280.0),
+        ];
+        let cavern_powerups = vec![
+            Bundle::powerup(710.0, 270.0, PowerUpType::SpeedBoost),
+        ];
+
+        let cavern_room = Room {
+            terrain: Terrain::new(cavern_platforms),
+            world: World::new(cavern_enemies.into_iter().chain(cavern_coins).chain(cavern_powerups)),
+            warps: vec![
+                Warp::door(60.0, 502.0, 0, 180.0, 518.0),
+            ],
+            theme: Some(Theme::Ice),
+            music: Music::Ice,
+            bounds: Rect::new(0.0, 0.0, 900.0, 600.0),
+            camera_zones: Vec::new(),
+        };
+
+        Level {
+            rooms: vec![main_room, cavern_room],
+            current_room: 0,
+            theme: Theme::Underground,
+            camera: CameraSettings::standard(),
+            goal_x: 2150.0,
+            goal_y: 350.0,
+            par_time: 45.0,
+        }
+    }
+
+    fn create_level_3() -> Self {
+        // World 1-3: Castle level with challenging jumps
+        let platforms = vec![
+            Platform::new(0.0, 550.0, 200.0, 50.0),       // Start
+            Platform::new(300.0, 500.0, 80.0, 20.0),      // Small platforms
+            Platform::new(480.0, 450.0, 80.0, 20.0),
+            Platform::new(660.0, 400.0, 80.0, 20.0),
+            Platform::new(840.0, 350.0, 80.0, 20.0),      // Ascending
+            Platform::new(1020.0, 300.0, 100.0, 20.0),    // Peak
+            Platform::new(1200.0, 250.0, 150.0, 20.0),    // High castle area
+            Platform::new(1450.0, 300.0, 80.0, 20.0),     // Descending
+            Platform::new(1630.0, 350.0, 80.0, 20.0),
+            Platform::new(1810.0, 400.0, 80.0, 20.0),
+            Platform::new(1990.0, 450.0, 80.0, 20.0),
+            Platform::new(2170.0, 500.0, 200.0, 50.0),    // Final area
+        ];
+        
+        let enemies = vec![
+            Bundle::enemy(350.0, 470.0),
+            Bundle::enemy(530.0, 420.0),
+            Bundle::enemy(710.0, 370.0),
+            Bundle::enemy(890.0, 320.0),
+            Bundle::enemy(1070.0, 270.0),
+            Bundle::enemy(1300.0, 220.0),
+            Bundle::enemy(1500.0, 270.0),
+            Bundle::enemy(1680.0, 320.0),
+            Bundle::enemy(1860.0, 370.0),
+            Bundle::enemy(2040.0, 420.0),
+        ];
+        
+        let coins = vec![
+            Bundle::coin(340.0, 480.0),
+            Bundle::coin(520.0, 430.0),
+            Bundle::coin(700.0, 380.0),
+            Bundle::coin(880.0, 330.0),
+            Bundle::coin(1070.0, 280.0),
+            Bundle::coin(1325.0, 230.0),
+            Bundle::coin(1490.0, 280.0),
+            Bundle::coin(1670.0, 330.0),
+            Bundle::coin(1850.0, 380.0),
+            Bundle::coin(2030.0, 430.0),
+            Bundle::coin(2220.0, 480.0),
+            Bundle::coin(2270.0, 480.0),
+        ];
+        
+        let powerups = vec![
+            Bundle::powerup(380.0, 470.0, PowerUpType::JumpBoost),
+            Bundle::powerup(1325.0, 220.0, PowerUpType::SpeedBoost),
+            Bundle::powerup(2220.0, 470.0, PowerUpType::JumpBoost),
+        ];
+
+        let warps = vec![
+            Warp::door(140.0, 502.0, 1, 60.0, 518.0),
+        ];
+
+        let main_room = Room {
+            terrain: Terrain::new(platforms),
+            world: World::new(enemies.into_iter().chain(coins).chain(powerups)),
+            warps,
+            theme: None,
+            music: Music::Castle,
+            bounds: Rect::new(0.0, -250.0, 2370.0, 850.0),
+            // The high castle area is an arena that holds until it is cleared
+            camera_zones: vec![
+                CameraZone {
+                    area: Rect::new(1150.0, -250.0, 800.0, 850.0),
+                    mode: CameraMode::Locked { x: 1150.0, y: 0.0 },
+                },
+            ],
+        };
+
+        // Tower climb above the clouds that comes out in the castle arena
+        let mut tower_platforms = vec![
+            Platform::new(0.0, 550.0, 800.0, 50.0),       // Floor
+            Platform::new(0.0, -800.0, 20.0, 1350.0),     // Left wall
+            Platform::new(780.0, -800.0, 20.0, 1350.0),   // Right wall
+        ];
+        let mut tower_coins = Vec::new();
+        for step in 0..12 {
+            let x = if step % 2 == 0 { 150.0 } else { 450.0 };
+            let y = 450.0 - step as f32 * 100.0;
+            tower_platforms.push(Platform::new(x, y, 200.0, 20.0));
+            tower_coins.push(Bundle::coin(x + 92.0, y - 30.0));
+        }
+
+        let tower_enemies = vec![
+            Bundle::enemy(200.0, 26.0),
+            Bundle::enemy(500.0, -274.0),
+        ];
+        let tower_powerups = vec![
+            Bundle::powerup(500.0, -70.0, PowerUpType::JumpBoost),
+        ];
+
+        let tower_room = Room {
+            terrain: Terrain::new(tower_platforms),
+            world: World::new(tower_enemies.into_iter().chain(tower_coins).chain(tower_powerups)),
+            warps: vec![
+                Warp::door(60.0, 502.0, 0, 140.0, 518.0),
+                Warp::door(584.0, -698.0, 0, 1210.0, 218.0),
+            ],
+            theme: Some(Theme::Sky),
+            music: Music::Sky,
+            bounds: Rect::new(0.0, -800.0, 800.0, 1400.0),
+            camera_zones: vec![
+                CameraZone {
+                    area: Rect::new(0.0, -800.0, 800.0, 1400.0),
+                    mode: CameraMode::VerticalOnly,
+                },
+            ],
+        };
+
+        // Precision jumps need to see further ahead and react faster
+        let camera = CameraSettings {
+            dead_zone_width: 48.0,
+            look_ahead: 120.0,
+            smoothing: 8.0,
+            ..CameraSettings::standard()
+        };
+
+        Level {
+            rooms: vec![main_room, tower_room],
+            current_room: 0,
+            theme: Theme::Castle,
+            camera,
+            goal_x: 2300.0,
+            goal_y: 450.0,
+            par_time: 60.0,
+        }
+    }
+
+    fn draw_goal(&self) {
+        // Draw flag pole goal (Mario-style)
+        // Pole
+        draw_rectangle(self.goal_x, self.goal_y - 100.0, 8.0, 150.0, BROWN);
+        // Flag
+        draw_rectangle(self.goal_x + 8.0, self.goal_y - 80.0, 40.0, 30.0, GREEN);
+        draw_rectangle(self.goal_x + 8.0, self.goal_y - 50.0, 40.0, 30.0, RED);
+        // Flag pole top
+        draw_rectangle(self.goal_x + 2.0, self.goal_y - 105.0, 12.0, 8.0, GOLD);
+    }
+
+    fn current_room(&self) -> &Room {
+        &self.rooms[self.current_room]
+    }
+
+    fn current_room_mut(&mut self) -> &mut Room {
+        &mut self.rooms[self.current_room]
+    }
+
+    fn in_main_room(&self) -> bool {
+        self.current_room == 0
+    }
+
+    // Coins collected and total coins across every room of the level
+    fn coin_count(&self) -> (usize, usize) {
+        let total: usize = self.rooms.iter().map(|room| room.world.coins).sum();
+        let left: usize = self.rooms.iter().map(|room| room.world.coins_left()).sum();
+        (total - left, total)
+    }
+
+    fn current_theme(&self) -> Theme {
+        self.current_room().theme.unwrap_or(self.theme)
+    }
+}
+
+impl SpatialGrid {
+    fn new(rects: &[Rect], cell_size: f32) -> Self {
+        let Some(first) = rects.first() else {
+            return SpatialGrid::default();
+        };
+        let extent = rects.iter().fold(*first, |extent, rect| extent.combine_with(*rect));
+        let mut grid = SpatialGrid {
+            origin: extent.point(),
+            cell_size,
+            columns: ((extent.w / cell_size).ceil() as usize).max(1),
+            rows: ((extent.h / cell_size).ceil() as usize).max(1),
+            cells: Vec::new(),
+        };
+        grid.cells = vec![Vec::new(); grid.columns * grid.rows];
+        for (index, rect) in rects.iter().enumerate() {
+            let (left, top, right, bottom) = grid.cells_covering(*rect);
+            for row in top..=bottom {
+                for column in left..=right {
+                    grid.cells[row * grid.columns + column].push(GridEntry { index, column: left, row: top });
+                }
+            }
+        }
+        grid
+    }
+
+    // Columns and rows an area touches, clamped to the grid
+    fn cells_covering(&self, area: Rect) -> (usize, usize, usize, usize) {
+        let cell = |value: f32, origin: f32, count: usize| {
+            (((value - origin) / self.cell_size).max(0.0) as usize).min(count - 1)
+        };
+        (
+            cell(area.x, self.origin.x, self.columns),
+            cell(area.y, self.origin.y, self.rows),
+            cell(area.x + area.w, self.origin.x, self.columns),
+            cell(area.y + area.h, self.origin.y, self.rows),
+        )
+    }
+
+    // Drops an entry from the cells its rectangle was filed under
+    fn remove(&mut self, index: usize, rect: Rect) {
+        if self.cells.is_empty() {
+            return;
+        }
+        let (left, top, right, bottom) = self.cells_covering(rect);
+        for row in top..=bottom {
+            for column in left..=right {
+                self.cells[row * self.columns + column].retain(|entry| entry.index != index);
+            }
+        }
+    }
+
+    // Indices of everything sharing a cell with the area, each reported once.
+    // Callers still test the actual overlap
+    fn query(&self, area: Rect) -> impl Iterator<Item = usize> + '_ {
+        let (left, top, right, bottom) = if self.cells.is_empty() {
+            (1, 1, 0, 0) // Nothing indexed, so the ranges below are empty
+        } else {
+            self.cells_covering(area)
+        };
+        (top..=bottom)
+            .flat_map(move |row| (left..=right).map(move |column| (column, row)))
+            .flat_map(move |(column, row)| {
+                // Entries spanning several cells only count in the first one the area shares
+                self.cells[row * self.columns + column].iter()
+                    .filter(move |entry| entry.column.max(left) == column && entry.row.max(top) == row)
+                    .map(|entry| entry.index)
+            })
+    }
+}
+
+impl Terrain {
+    fn new(platforms: Vec<Platform>) -> Self {
+        Terrain::with_cell_size(platforms, GRID_CELL_SIZE)
+    }
+
+    fn with_cell_size(platforms: Vec<Platform>, cell_size: f32) -> Self {
+        let rects: Vec<Rect> = platforms.iter().map(Platform::rect).collect();
+        Terrain { grid: SpatialGrid::new(&rects, cell_size), platforms: platforms.into_iter().map(Some).collect() }
+    }
+
+    // Every platform still standing
+    fn platforms(&self) -> impl Iterator<Item = &Platform> {
+        self.platforms.iter().flatten()
+    }
+
+    // Platforms that might touch the area, with their indices
+    fn near(&self, area: Rect) -> impl Iterator<Item = (usize, &Platform)> {
+        self.grid.query(area).filter_map(|index| Some((index, self.platforms[index].as_ref()?)))
+    }
+
+    // Empties the platform's slot and drops it from the grid, without
+    // touching anything else
+    fn remove(&mut self, index: usize) -> Option<Platform> {
+        let platform = self.platforms[index].take()?;
+        self.grid.remove(index, platform.rect());
+        Some(platform)
+    }
+}
+
+impl World {
+    fn new(bundles: impl IntoIterator<Item = Bundle>) -> Self {
+        World::with_cell_size(bundles, GRID_CELL_SIZE)
+    }
+
+    fn with_cell_size(bundles: impl IntoIterator<Item = Bundle>, cell_size: f32) -> Self {
+        let mut world = World {
+            transforms: Vec::new(),
+            velocities: Vec::new(),
+            colliders: Vec::new(),
+            bodies: Vec::new(),
+            sprites: Vec::new(),
+            pickups: Vec::new(),
+            hostiles: Vec::new(),
+            patrols: Vec::new(),
+            corpses: Vec::new(),
+            hostile_entities: Vec::new(),
+            pickup_grid: SpatialGrid::default(),
+            pickup_entities: Vec::new(),
+            coins: 0,
+        };
+        // Kept empty until the player walks in
+        world.reserve();
+        for bundle in bundles {
+            let entity = world.reserve();
+            world.insert(entity, bundle);
+        }
+        world.pickup_entities = world.entities().filter(|&entity| world.pickups[entity].is_some()).collect();
+        let rects: Vec<Rect> = world.pickup_entities.iter().filter_map(|&entity| world.rect(entity)).collect();
+        world.pickup_grid = SpatialGrid::new(&rects, cell_size);
+        world.coins = world.coins_left();
+        world
+    }
+
+    // Adds an empty slot
+    fn reserve(&mut self) -> Entity {
+        self.transforms.push(None);
+        self.velocities.push(None);
+        self.colliders.push(None);
+        self.bodies.push(None);
+        self.sprites.push(None);
+        self.pickups.push(None);
+        self.hostiles.push(None);
+        self.patrols.push(None);
+        self.corpses.push(None);
+        self.transforms.len() - 1
+    }
+
+    // Fills a slot, replacing whatever was there
+    fn insert(&mut self, entity: Entity, bundle: Bundle) {
+        self.despawn(entity);
+        self.transforms[entity] = Some(bundle.transform);
+        self.velocities[entity] = bundle.velocity;
+        self.colliders[entity] = Some(bundle.collider);
+        self.bodies[entity] = bundle.body;
+        self.sprites[entity] = bundle.sprite;
+        self.pickups[entity] = bundle.pickup;
+        self.hostiles[entity] = bundle.hostile;
+        self.patrols[entity] = bundle.patrol;
+        self.corpses[entity] = bundle.corpse;
+        if bundle.hostile.is_some() {
+            self.hostile_entities.push(entity);
+        }
+    }
+
+    // Empties a slot, handing back its components
+    fn take(&mut self, entity: Entity) -> Option<Bundle> {
+        let bundle = Bundle {
+            transform: self.transforms[entity]?,
+            collider: self.colliders[entity]?,
+            velocity: self.velocities[entity].take(),
+            body: self.bodies[entity].take(),
+            sprite: self.sprites[entity].take(),
+            pickup: self.pickups[entity].take(),
+            hostile: self.hostiles[entity],
+            patrol: self.patrols[entity].take(),
+            corpse: self.corpses[entity].take(),
+        };
+        self.despawn(entity);
+        Some(bundle)
+    }
+
+    fn despawn(&mut self, entity: Entity) {
+        self.transforms[entity] = None;
+        self.velocities[entity] = None;
+        self.colliders[entity] = None;
+        self.bodies[entity] = None;
+        self.sprites[entity] = None;
+        self.pickups[entity] = None;
+        self.patrols[entity] = None;
+        self.corpses[entity] = None;
+        self.remove_hostile(entity);
+    }
+
+    fn remove_hostile(&mut self, entity: Entity) {
+        if self.hostiles[entity].take().is_some() {
+            self.hostile_entities.retain(|&hostile| hostile != entity);
+        }
+    }
+
+    // Every slot, despawned ones included
+    fn entities(&self) -> std::ops::Range<Entity> {
+        0..self.transforms.len()
+    }
+
+    // Where the entity is and how much space it takes up
+    fn rect(&self, entity: Entity) -> Option<Rect> {
+        let (Some(transform), Some(collider)) = (self.transforms[entity], self.colliders[entity]) else {
+            return None;
+        };
+        Some(Rect::new(transform.x, transform.y, collider.width, collider.height))
+    }
+
+    fn on_ground(&self, entity: Entity) -> bool {
+        self.bodies[entity].is_some_and(|body| body.on_ground)
+    }
+
+    fn animator_mut(&mut self, entity: Entity) -> Option<&mut Animator> {
+        self.sprites[entity].as_mut().and_then(|sprite| sprite.animator.as_mut())
+    }
+
+    fn coins_left(&self) -> usize {
+        self.pickups.iter().filter(|pickup| matches!(pickup, Some(Pickup::Coin))).count()
+    }
+
+    // Whether any hostile entity stands inside the area
+    fn hostile_within(&self, area: Rect) -> bool {
+        self.hostile_entities.iter().any(|&entity| {
+            self.transforms[entity].is_some_and(|transform| area.contains(vec2(transform.x, transform.y)))
+        })
+    }
+
+    // Movement and animation, for everything that has the components
+    fn update(&mut self, dt: f32, terrain: &Terrain) {
+        self.patrol_system(dt, terrain);
+        self.physics_system(dt, terrain);
+        self.animation_system(dt);
+    }
+
+    fn patrol_system(&mut self, dt: f32, terrain: &Terrain) {
+        for entity in self.entities() {
+            let (Some(transform), Some(collider), Some(patrol)) =
+                (&mut self.transforms[entity], self.colliders[entity], &mut self.patrols[entity]) else {
+                continue;
+            };
+            transform.x += patrol.speed * patrol.direction * dt;
+
+            // Feet are checked a little below the walker
+            let feet = Rect::new(transform.x, transform.y, collider.width, collider.height + 10.0);
+            for (_, platform) in terrain.near(feet) {
+                // Standing on this platform
+                if transform.x + collider.width > platform.x &&
+                   transform.x < platform.x + platform.width &&
+                   transform.y + collider.height >= platform.y &&
+                   transform.y + collider.height <= platform.y + 10.0 {
+                    // Turn around at its edges
+                    if patrol.direction > 0.0 && transform.x + collider.width >= platform.x + platform.width {
+                        patrol.direction = -1.0;
+                    } else if patrol.direction < 0.0 && transform.x <= platform.x {
+                        patrol.direction = 1.0;
+                    }
+                }
+            }
+        }
+    }
+
+    // Gravity, then platforms: bodies are pushed out of them horizontally and
+    // then vertically, anything else only lands on top
+    fn physics_system(&mut self, dt: f32, terrain: &Terrain) {
+        let gravity = 800.0;
+        for entity in self.entities() {
+            let (Some(transform), Some(collider), Some(velocity)) =
+                (&mut self.transforms[entity], self.colliders[entity], &mut self.velocities[entity]) else {
+                continue;
+            };
+            velocity.y += gravity * dt;
+            let start = Rect::new(transform.x, transform.y, collider.width, collider.height);
+
+            // Only platforms near the whole move this tick can be touched
+            let reach = start.combine_with(Rect::new(
+                transform.x + velocity.x * dt,
+                transform.y + velocity.y * dt,
+                collider.width,
+                collider.height,
+            ));
+            let touching = |transform: &Transform, platform: &Platform| {
+                transform.x < platform.x + platform.width &&
+                transform.x + collider.width > platform.x &&
+                transform.y < platform.y + platform.height &&
+                transform.y + collider.height > platform.y
+            };
+
+            let Some(body) = &mut self.bodies[entity] else {
+                transform.x += velocity.x * dt;
+                transform.y += velocity.y * dt;
+                for (_, platform) in terrain.near(reach) {
+                    if touching(transform, platform) && velocity.y > 0.0 {
+                        transform.y = platform.y - collider.height;
+                        velocity.y = 0.0;
+                    }
+                }
+                continue;
+            };
+
+            // Move horizontally first
+            transform.x += velocity.x * dt;
+            for (_, platform) in terrain.near(reach) {
+                if touching(transform, platform) {
+                    if velocity.x > 0.0 {
+                        // Moving right, hit left side of platform
+                        transform.x = platform.x - collider.width;
+                    } else if velocity.x < 0.0 {
+                        // Moving left, hit right side of platform
+                        transform.x = platform.x + platform.width;
+                    }
+                    velocity.x = 0.0;
+                }
+            }
+
+            // Move vertically
+            transform.y += velocity.y * dt;
+            body.on_ground = false;
+            body.bumped = None;
+            for (index, platform) in terrain.near(reach) {
+                if touching(transform, platform) {
+                    if velocity.y > 0.0 {
+                        // Falling down, hit top of platform
+                        transform.y = platform.y - collider.height;
+                        velocity.y = 0.0;
+                        body.on_ground = true;
+                    } else if velocity.y < 0.0 {
+                        // Moving up, hit bottom of platform
+                        transform.y = platform.y + platform.height;
+                        velocity.y = 0.0;
+                        body.bumped = Some(index);
+                    }
+                }
+            }
+        }
+    }
+
+    fn animation_system(&mut self, dt: f32) {
+        for entity in self.entities() {
+            let Some(animator) = self.sprites[entity].as_mut().and_then(|sprite| sprite.animator.as_mut()) else {
+                continue;
+            };
+            // Bodies animate from how they move, walkers face where they walk
+            if let (Some(body), Some(velocity)) = (self.bodies[entity], self.velocities[entity]) {
+                animator.set_state(body.anim_state(velocity));
+            }
+            if let Some(patrol) = self.patrols[entity] {
+                animator.flip_x = patrol.direction < 0.0;
+            }
+            animator.update(dt);
+            if self.corpses[entity].is_some() && animator.finished() {
+                self.despawn(entity);
+            }
+        }
+    }
+
+    // Keeps an entity between two screen edges, returns true if that pushes
+    // it into solid ground and it gets crushed
+    fn confine(&mut self, entity: Entity, left: f32, right: f32, terrain: &Terrain) -> bool {
+        let (Some(transform), Some(collider)) = (&mut self.transforms[entity], self.colliders[entity]) else {
+            return false;
+        };
+        if transform.x < left {
+            transform.x = left;
+        } else if transform.x + collider.width > right {
+            transform.x = right - collider.width;
+        } else {
+            return false;
+        }
+        if let Some(velocity) = &mut self.velocities[entity] {
+            velocity.x = 0.0;
+        }
+
+        let Some(rect) = self.rect(entity) else {
+            return false;
+        };
+        terrain.near(rect).any(|(_, platform)| overlapping(rect, platform.rect()))
+    }
+
+    // Hostiles the player falls onto are stomped, touching any other hurts.
+    // Returns true if the player was hurt. Hostiles move every tick, so rather
+    // than rebuild a grid for them this walks the dense list: one rectangle
+    // test each, around a microsecond for 500 of them in --bench-broadphase
+    fn damage_system(&mut self, player: &mut Player, events: &mut Vec<GameEvent>) -> bool {
+        let (Some(player_rect), Some(velocity)) = (self.rect(PLAYER), self.velocities[PLAYER]) else {
+            return false;
+        };
+        // Stomps are judged on how the player was moving when the tick began,
+        // so bouncing off one enemy doesn't count as walking into the next
+        let falling = velocity.y > 0.0;
+        let mut hurt = false;
+        let mut next = 0;
+        while let Some(&entity) = self.hostile_entities.get(next) {
+            let Some(at) = self.rect(entity).filter(|rect| overlapping(*rect, player_rect)) else {
+                next += 1;
+                continue;
+            };
+            if falling && player_rect.y < at.y {
+                // Stomping takes the entity off the hostile list, so the next one slides into its place
+                self.stomp(entity);
+                if let Some(velocity) = &mut self.velocities[PLAYER] {
+                    velocity.y = -300.0;
+                }
+                events.push(GameEvent::EnemyStomped { at, chain: player.stomp_chain });
+                player.stomp_chain += 1;
+            } else {
+                // Touching several enemies at once still only costs one life
+                hurt = true;
+                next += 1;
+            }
+        }
+        hurt
+    }
+
+    // A stomped enemy stops moving and hurting, and plays its squash
+    fn stomp(&mut self, entity: Entity) {
+        self.remove_hostile(entity);
+        self.patrols[entity] = None;
+        self.velocities[entity] = None;
+        self.corpses[entity] = Some(Corpse);
+        if let Some(animator) = self.animator_mut(entity) {
+            animator.set_state(AnimState::Die);
+        }
+    }
+
+    // Collected pickups lose their pickup and sprite, leaving nothing to touch or draw
+    fn pickup_system(&mut self, area: Rect, events: &mut Vec<GameEvent>) {
+        for index in self.pickup_grid.query(area) {
+            let entity = self.pickup_entities[index];
+            let Some(at) = self.rect(entity).filter(|rect| overlapping(*rect, area)) else {
+                continue;
+            };
+            let Some(pickup) = self.pickups[entity].take() else {
+                continue;
+            };
+            self.sprites[entity] = None;
+            events.push(match pickup {
+                Pickup::Coin => GameEvent::CoinCollected { at },
+                Pickup::PowerUp(power_type) => GameEvent::PowerUpCollected { at, power_type },
+            });
+        }
+    }
+
+    // The player goes on top of everything else
+    fn draw(&self, sprites: &Sprites, palette: &Palette) {
+        for player_pass in [false, true] {
+            for entity in self.entities() {
+                let (Some(at), Some(sprite)) = (self.rect(entity), &self.sprites[entity]) else {
+                    continue;
+                };
+                if (sprite.kind == SpriteKind::Player) == player_pass {
+                    sprite.kind.draw(at, sprite.animator.as_ref(), sprites, palette);
+                }
+            }
+        }
+    }
+}
+
+impl Room {
+    fn active_camera_zone(&self, player: Rect) -> Option<&CameraZone> {
+        let center = player.center();
+        self.camera_zones.iter().find(|zone| {
+            if !zone.area.contains(center) {
+                return false;
+            }
+            match zone.mode {
+                // Arena locks release once every enemy inside is defeated
+                CameraMode::Locked { .. } => self.world.hostile_within(zone.area),
+                _ => true,
+            }
+        })
+    }
+}
+
+impl RunStats {
+    fn observe(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::CoinCollected { .. } => self.coins += 1,
+            GameEvent::EnemyStomped { .. } => self.stomps += 1,
+            GameEvent::PlayerDied { .. } => self.deaths += 1,
+            GameEvent::LevelCompleted { .. } => self.levels_cleared += 1,
+            GameEvent::Ticked { dt } => self.time += dt,
+            _ => {}
+        }
+    }
+}
+
+impl DeathCause {
+    const ALL: [DeathCause; 3] = [DeathCause::Enemy, DeathCause::Fall, DeathCause::Crushed];
+
+    fn label(self) -> &'static str {
+        match self {
+            DeathCause::Enemy => "enemies",
+            DeathCause::Fall => "falls",
+            DeathCause::Crushed => "crushed",
+        }
+    }
+
+    // Name used in the death log
+    fn key(self) -> &'static str {
+        match self {
+            DeathCause::Enemy => "enemy",
+            DeathCause::Fall => "fall",
+            DeathCause::Crushed => "crushed",
+        }
+    }
+
+    fn parse(key: &str) -> Option<DeathCause> {
+        DeathCause::ALL.into_iter().find(|cause| cause.key() == key)
+    }
+}
+
+impl LifetimeStats {
+    fn new() -> Self {
+        LifetimeStats {
+            jumps: 0,
+            deaths: [0; DeathCause::ALL.len()],
+            stomps: 0,
+            coins: 0,
+            distance: 0.0,
+            play_time: 0.0,
+            best_times: [None; LEVEL_COUNT],
+            attempts: [0; LEVEL_COUNT],
+        }
+    }
+
+    fn observe(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::LevelStarted { level, .. } => self.attempts[level] += 1,
+            GameEvent::Ticked { dt } => self.play_time += dt,
+            GameEvent::Moved { distance } => self.distance += distance,
+            GameEvent::Jumped => self.jumps += 1,
+            GameEvent::CoinCollected { .. } => self.coins += 1,
+            GameEvent::EnemyStomped { .. } => self.stomps += 1,
+            GameEvent::PlayerDied { cause, .. } => self.deaths[cause as usize] += 1,
+            GameEvent::LevelCompleted { level, time, .. } => {
+                let best = &mut self.best_times[level];
+                *best = Some(best.map_or(time, |best| best.min(time)));
+            }
+            _ => {}
+        }
+    }
+
+    fn total_deaths(&self) -> i32 {
+        self.deaths.iter().sum()
+    }
+}
+
+impl AchievementRule {
+    fn met(&self, achievements: &Achievements, stats: &LifetimeStats, event: &GameEvent) -> bool {
+        match (self, event) {
+            (AchievementRule::Stomps(target), GameEvent::EnemyStomped { .. }) => stats.stomps >= *target,
+            (AchievementRule::NoCoins { level }, GameEvent::LevelCompleted { level: finished, coins, .. }) => {
+                finished == level && *coins == 0
+            }
+            (AchievementRule::NoDamage { level }, GameEvent::LevelCompleted { level: finished, .. }) => {
+                finished == level && !achievements.hurt_this_level
+            }
+            (AchievementRule::AllCoins, GameEvent::LevelCompleted { coins, total_coins, .. }) => coins == total_coins,
+            (AchievementRule::UnderPar, GameEvent::LevelCompleted { time, par_time, .. }) => time <= par_time,
+            _ => false,
+        }
+    }
+}
+
+impl Achievements {
+    fn new() -> Self {
+        Achievements {
+            unlocked: [false; ACHIEVEMENTS.len()],
+            hurt_this_level: false,
+        }
+    }
+
+    // Tracks the level in play, then unlocks anything the event completes.
+    // Counted rules read the lifetime stats, which see each event first
+    fn observe(&mut self, event: &GameEvent, stats: &LifetimeStats, toasts: &mut Toasts) -> bool {
+        match event {
+            GameEvent::LevelStarted { .. } => self.hurt_this_level = false,
+            GameEvent::PlayerHurt | GameEvent::PlayerDied { .. } => self.hurt_this_level = true,
+            _ => {}
+        }
+        let mut changed = false;
+        for (index, achievement) in ACHIEVEMENTS.iter().enumerate() {
+            if !self.unlocked[index] && achievement.rule.met(self, stats, event) {
+                self.unlocked[index] = true;
+                toasts.push(achievement.name);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    // Menu line, with progress for counted achievements
+    fn describe(&self, index: usize, stats: &LifetimeStats) -> String {
+        let achievement = &ACHIEVEMENTS[index];
+        match achievement.rule {
+            AchievementRule::Stomps(target) if !self.unlocked[index] => {
+                format!("{}: {} ({}/{})", achievement.name, achievement.description, stats.stomps.min(target), target)
+            }
+            _ => format!("{}: {}", achievement.name, achievement.description),
+        }
+    }
+}
+
+impl Toasts {
+    fn new() -> Self {
+        Toasts { queue: Vec::new() }
+    }
+
+    fn push(&mut self, name: &'static str) {
+        self.queue.push((name, 0.0));
+    }
+
+    fn update(&mut self, dt: f32) {
+        if let Some((_, time)) = self.queue.first_mut() {
+            *time += dt;
+            if *time >= TOAST_TIME {
+                self.queue.remove(0);
+            }
+        }
+    }
+
+    fn draw(&self, viewport: &Viewport) {
+        let Some((name, time)) = self.queue.first() else {
+            return;
+        };
+        // Slides down from above the screen and back up again
+        let shown = (time * 4.0).min(1.0).min((TOAST_TIME - time) * 4.0);
+        let top = viewport.anchor(Anchor::Top, -170.0, -70.0 + shown * 80.0);
+        draw_rectangle(top.x, top.y, 340.0 * viewport.scale, 60.0 * viewport.scale, Color::new(0.0, 0.0, 0.0, 0.8));
+        draw_rectangle_lines(top.x, top.y, 340.0 * viewport.scale, 60.0 * viewport.scale, 2.0 * viewport.scale, GOLD);
+        viewport.text("Achievement unlocked!", Anchor::Top, 0.0, -45.0 + shown * 80.0, 20.0, GOLD);
+        viewport.text(name, Anchor::Top, 0.0, -20.0 + shown * 80.0, 30.0, WHITE);
+    }
+}
+
+impl Game {
+    fn new(sprites: Sprites) -> Self {
+        let levels = (0..LEVEL_COUNT).map(Level::create).collect();
+        let save = SaveData::load();
+
+        let mut game = Game {
+            player: Player::new(),
+            camera: Camera::new(),
+            theme_art: ThemeArt::new(),
+            sprites,
+            particles: Particles::new(512),
+            popups: Popups::new(32),
+            audio: Audio::new(),
+            canvas: Canvas::new(),
+            input: Input::new(save.settings.bindings),
+            save,
+            fullscreen: false,
+            level_start_score: 0,
+            level_start_coins: 0,
+            level_start_lives: STARTING_LIVES,
+            transition: None,
+            run: RunStats::default(),
+            level_time: 0.0,
+            events: Vec::new(),
+            spare_events: Vec::new(),
+            toasts: Toasts::new(),
+            levels,
+            current_level: 0,
+            level_completed: false,
+        };
+        game.apply_settings();
+        game.spawn_player();
+        game
+    }
+
+    fn reset_game(&mut self) {
+        self.player = Player::new();
+        self.camera = Camera::new();
+        self.current_level = 0;
+        self.level_completed = false;
+        self.transition = None;
+        
+        // Reset all levels
+        self.levels = (0..LEVEL_COUNT).map(Level::create).collect();
+        self.spawn_player();
+        self.snap_camera();
+    }
+
+    // Run starting at the given level, used by the menus
+    fn start_level(&mut self, progress: Progress) {
+        self.reset_game();
+        self.run = RunStats::default();
+        self.run.continues_used = progress.continues_used;
+        self.current_level = progress.level;
+        self.player.score = progress.score;
+        self.player.lives = progress.lives;
+        self.respawn_player();
+        self.mark_level_start();
+    }
+
+    // Spends a continue: the current level starts over with a fresh score and lives
+    fn continue_level(&mut self) {
+        self.run.continues_used += 1;
+        self.level_start_score = 0;
+        self.level_start_lives = STARTING_LIVES;
+        self.restart_level();
+    }
+
+    // Hands this tick's events to every system that reacts to them. Events
+    // published while handling them, like extra lives, go out in the same call
+    fn dispatch_events(&mut self) {
+        let mut events = std::mem::take(&mut self.spare_events);
+        let mut save = false;
+        while !self.events.is_empty() {
+            std::mem::swap(&mut events, &mut self.events);
+            for event in &events {
+                self.award(event);
+                self.audio.observe(event);
+                self.particles.observe(event);
+                self.run.observe(event);
+                self.save.stats.observe(event);
+                self.log_death(event);
+                save |= self.save.achievements.observe(event, &self.save.stats, &mut self.toasts);
+                // Stats are written at the end of each life and level
+                save |= matches!(event, GameEvent::PlayerDied { .. } | GameEvent::LevelCompleted { .. });
+            }
+            events.clear();
+        }
+        self.spare_events = events;
+        if save {
+            self.save.save();
+        }
+    }
+
+    // Appends deaths to the log read by the --heatmap tool
+    fn log_death(&self, event: &GameEvent) {
+        let GameEvent::PlayerDied { cause, x, y } = *event else {
+            return;
+        };
+        let record = DeathRecord {
+            level: self.current_level,
+            room: self.current_level().current_room,
+            x,
+            y,
+            cause,
+        };
+        let written = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(DEATH_LOG_PATH)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, (record.to_json() + "\n").as_bytes()));
+        if let Err(error) = written {
+            println!("Could not write {}: {}", DEATH_LOG_PATH, error);
+        }
+    }
+
+    fn award(&mut self, event: &GameEvent) {
+        let at = self.player_rect();
+        self.player.award(event, at, &mut self.popups, &mut self.events);
+    }
+
+    fn continues_left(&self) -> i32 {
+        CONTINUES_PER_RUN - self.run.continues_used
+    }
+
+    // Records the start of the current level for Restart Level and Continue
+    fn mark_level_start(&mut self) {
+        self.level_time = 0.0;
+        self.events.push(GameEvent::LevelStarted { level: self.current_level, music: self.current_room().music });
+        self.level_start_score = self.player.score;
+        self.level_start_coins = self.player.coins;
+        self.level_start_lives = self.player.lives;
+        self.save.unlocked_levels = self.save.unlocked_levels.max(self.current_level + 1);
+        self.save.progress = Some(Progress {
+            level: self.current_level,
+            score: self.player.score,
+            lives: self.player.lives,
+            continues_used: self.run.continues_used,
+        });
+        self.save.save();
+    }
+
+    // Ends the run as soon as the last life is gone. Taking a continue starts
+    // the level again, which writes a fresh save point, so declining, letting
+    // the countdown run out or quitting leaves nothing to continue from
+    fn game_over(&mut self) {
+        self.save.record_score(self.player.score);
+        self.save.progress = None;
+        self.save.save();
+    }
+
+    // Pushes saved settings to the window and input
+    fn apply_settings(&mut self) {
+        let settings = &self.save.settings;
+        if settings.fullscreen != self.fullscreen {
+            self.fullscreen = settings.fullscreen;
+            set_fullscreen(self.fullscreen);
+        }
+        self.input.bindings = settings.bindings;
+        self.audio.music_volume = settings.music_volume;
+        self.audio.sound_volume = settings.sound_volume;
+    }
+
+    // Puts the current level back to how it started, score, coins and lives
+    // included, so 1-ups can't be farmed by restarting
+    fn restart_level(&mut self) {
+        self.levels[self.current_level] = Level::create(self.current_level);
+        self.player.score = self.level_start_score;
+        self.player.coins = self.level_start_coins;
+        self.player.lives = self.level_start_lives;
+        self.transition = None;
+        self.respawn_player();
+        self.mark_level_start();
+    }
+
+    // Back to the start of the level after losing a life. Enemies and coins
+    // come back, the score and lives the player has now carry over
+    fn retry_level(&mut self) {
+        self.levels[self.current_level] = Level::create(self.current_level);
+        self.respawn_player();
+        self.mark_level_start();
+    }
+
+    fn next_level(&mut self) {
+        self.current_level += 1;
+        self.respawn_player();
+        self.level_completed = false;
+        self.mark_level_start();
+    }
+
+    fn current_level(&self) -> &Level {
+        &self.levels[self.current_level]
+    }
+
+    fn current_level_mut(&mut self) -> &mut Level {
+        &mut self.levels[self.current_level]
+    }
+
+    fn current_room(&self) -> &Room {
+        self.current_level().current_room()
+    }
+
+    fn world(&self) -> &World {
+        &self.current_room().world
+    }
+
+    fn world_mut(&mut self) -> &mut World {
+        &mut self.current_level_mut().current_room_mut().world
+    }
+
+    // A fresh player entity at the start of the current room
+    fn spawn_player(&mut self) {
+        self.world_mut().insert(PLAYER, Bundle::player(100.0, 480.0));
+    }
+
+    fn player_rect(&self) -> Rect {
+        self.world().rect(PLAYER).unwrap_or_default()
+    }
+
+    fn player_view(&self) -> PlayerView {
+        PlayerView {
+            rect: self.player_rect(),
+            facing_right: self.player.facing_right,
+            on_ground: self.world().on_ground(PLAYER),
+        }
+    }
+
+    // The player's entity moves along to the new room's world
+    fn switch_room(&mut self, room: usize) {
+        let level = self.current_level_mut();
+        let player = level.current_room_mut().world.take(PLAYER);
+        level.current_room = room;
+        if let Some(player) = player {
+            level.current_room_mut().world.insert(PLAYER, player);
+        }
+        self.particles.clear();
+        self.popups.clear();
+    }
+
+    // Through a warp, changing track only if the new room has its own
+    fn enter_room(&mut self, room: usize) {
+        let previous_music = self.current_room().music;
+        self.switch_room(room);
+        let music = self.current_room().music;
+        if music != previous_music {
+            self.audio.play_music(music);
+        }
+    }
+
+    // Every caller starts the level afterwards, and LevelStarted plays its music
+    fn respawn_player(&mut self) {
+        // Deaths always send the player back to the start of the main room
+        self.player.reset();
+        self.switch_room(0);
+        self.spawn_player();
+        self.snap_camera();
+    }
+
+    // Starts the death sequence; the Gameplay scene moves on once it's over
+    fn lose_life(&mut self, cause: DeathCause) {
+        let at = self.player_rect().center();
+        self.events.push(GameEvent::PlayerDied { cause, x: at.x, y: at.y });
+        let world = &mut self.levels[self.current_level].current_room_mut().world;
+        self.player.die(world);
+    }
+
+    fn death_finished(&self) -> bool {
+        self.player.dying.is_some_and(|time| {
+            time > DEATH_MIN_TIME && self.player_rect().y > self.camera.y + VIRTUAL_HEIGHT
+        })
+    }
+
+    fn run_level_action(&mut self, action: LevelAction) {
+        match action {
+            LevelAction::Warp { room, x, y } => {
+                self.enter_room(room);
+                let world = self.world_mut();
+                world.transforms[PLAYER] = Some(Transform { x, y });
+                world.velocities[PLAYER] = Some(Velocity::default());
+                self.snap_camera();
+            }
+        }
+    }
+
+    // Player's centre on the virtual screen, where iris wipes close
+    fn player_focus(&self) -> Vec2 {
+        let center = self.player_rect().center();
+        vec2(center.x - snap_to_pixel(self.camera.x), center.y - snap_to_pixel(self.camera.y))
+    }
+
+    fn snap_camera(&mut self) {
+        let bounds = self.current_room().bounds;
+        self.camera.snap_to(self.player_view(), bounds);
+    }
+
+    fn check_warps(&mut self) -> bool {
+        let player = self.player_view();
+        let warp = self.current_room().warps.iter()
+            .find(|warp| warp.can_enter(player, &self.input))
+            .map(|warp| (warp.target_room, warp.target_x, warp.target_y));
+
+        if let Some((room, x, y)) = warp {
+            self.events.push(GameEvent::Warped);
+            self.transition = Some(Transition::new(TransitionKind::Wipe, LevelAction::Warp { room, x, y }));
+            return true;
+        }
+        false
+    }
+
+    // Display toggles work on every screen, returns true when one changed
+    fn handle_display_keys(&mut self) -> bool {
+        if !is_key_pressed(KeyCode::F11) && !is_key_pressed(KeyCode::F10) {
+            return false;
+        }
+        let settings = &mut self.save.settings;
+        if is_key_pressed(KeyCode::F11) {
+            settings.fullscreen = !settings.fullscreen;
+        }
+        if is_key_pressed(KeyCode::F10) {
+            settings.integer_scaling = !settings.integer_scaling;
+        }
+        self.apply_settings();
+        self.save.save();
+        true
+    }
+
+    // Advances the level by one frame; scenes check level_completed and lives afterwards
+    fn update_level(&mut self, dt: f32) {
+        // Play time stats count real time, the level clock and par use game time
+        self.events.push(GameEvent::Ticked { dt });
+        // Slowed down for the accessibility game speed setting
+        let dt = dt * self.save.settings.game_speed as f32 / 100.0;
+        self.level_time += dt;
+
+        // The level is frozen while a warp transition plays
+        if let Some(transition) = &mut self.transition {
+            let action = transition.update(dt);
+            if transition.finished() {
+                self.transition = None;
+            }
+            if let Some(action) = action {
+                self.run_level_action(action);
+            }
+            return;
+        }
+
+        // Input is locked and everything but the player and effects freezes
+        // while the death sequence plays
+        if self.player.dying.is_some() {
+            let world = &mut self.levels[self.current_level].current_room_mut().world;
+            self.player.update_dying(dt, world);
+            self.particles.update(dt);
+            self.popups.update(dt);
+            return;
+        }
+
+        if self.check_warps() {
+            return;
+        }
+
+        // Systems, in order. Each borrows the room's terrain immutably and its
+        // world mutably, so nothing is copied or deferred
+        self.move_player(dt);
+        self.particles.update(dt);
+        self.popups.update(dt);
+        self.break_bricks();
+        let hurt = self.resolve_damage();
+        self.collect_pickups();
+        if hurt {
+            self.events.push(GameEvent::PlayerHurt);
+            self.lose_life(DeathCause::Enemy);
+            return;
+        }
+
+        // Check goal collision
+        let level = self.current_level();
+        let player = self.player_rect();
+        if level.in_main_room() &&
+           player.x + player.w > level.goal_x &&
+           player.x < level.goal_x + 50.0 &&
+           player.y + player.h > level.goal_y - 100.0 &&
+           player.y < level.goal_y + 50.0 &&
+           !self.level_completed {
+            let (coins, total_coins) = level.coin_count();
+            self.events.push(GameEvent::LevelCompleted {
+                level: self.current_level,
+                time: self.level_time,
+                par_time: level.par_time,
+                coins,
+                total_coins,
+            });
+            self.level_completed = true;
+            return;
+        }
+
+        // Check if player fell off the world
+        if player.y > 700.0 {
+            self.lose_life(DeathCause::Fall);
+            return;
+        }
+        
+        let view = self.player_view();
+        let level = &mut self.levels[self.current_level];
+        let room = &mut level.rooms[level.current_room];
+        let zone = room.active_camera_zone(view.rect);
+        self.camera.update(dt, view, &level.camera, room.bounds, zone);
+
+        // Locked and auto-scrolling screens hold the player inside their edges
+        let edges = zone.and_then(|zone| match zone.mode {
+            CameraMode::Locked { x, .. } => Some((x, x + VIRTUAL_WIDTH)),
+            CameraMode::AutoScroll { .. } => {
+                let scrolling = self.camera.x < zone.area.x + zone.area.w - VIRTUAL_WIDTH;
+                Some((self.camera.x, if scrolling { self.camera.x + VIRTUAL_WIDTH } else { f32::INFINITY }))
+            }
+            CameraMode::VerticalOnly => None,
+        });
+        if let Some((left, right)) = edges &&
+           room.world.confine(PLAYER, left, right, &room.terrain) {
+            // Pushed into a wall by the screen edge
+            self.lose_life(DeathCause::Crushed);
+        }
+    }
+
+    // Input, then movement and animation for the whole room, then the dust
+    // and trails that follow the player
+    fn move_player(&mut self, dt: f32) {
+        let room = self.levels[self.current_level].current_room_mut();
+        let world = &mut room.world;
+        let was_on_ground = world.on_ground(PLAYER);
+        let was_facing_right = self.player.facing_right;
+        if self.player.handle_input(&self.input, world) {
+            self.events.push(GameEvent::Jumped);
+        }
+        let start = world.rect(PLAYER).unwrap_or_default();
+        world.update(dt, &room.terrain);
+
+        let (Some(end), Some(velocity)) = (world.rect(PLAYER), &mut world.velocities[PLAYER]) else {
+            return;
+        };
+        // Friction
+        velocity.x *= 0.8;
+        let moving = velocity.x.abs() > 10.0;
+        if end.x != start.x {
+            self.events.push(GameEvent::Moved { distance: (end.x - start.x).abs() });
+        }
+
+        let on_ground = world.on_ground(PLAYER);
+        let feet_x = end.x + end.w / 2.0;
+        let feet_y = end.y + end.h;
+        let turned = was_facing_right != self.player.facing_right;
+        if on_ground && (!was_on_ground || turned) {
+            self.particles.burst(&DUST, feet_x, feet_y);
+        }
+        if self.player.speed_boost > 1.0 && moving {
+            self.particles.emit(&SPEED_TRAIL, feet_x, feet_y - 8.0, dt);
+        }
+        if on_ground {
+            self.player.stomp_chain = 0;
+        }
+    }
+
+    // Bricks break when hit from below
+    fn break_bricks(&mut self) {
+        let room = self.levels[self.current_level].current_room_mut();
+        let Some(index) = room.world.bodies[PLAYER].and_then(|body| body.bumped) else {
+            return;
+        };
+        let terrain = &mut room.terrain;
+        let is_brick = terrain.platforms[index].as_ref().is_some_and(|platform| platform.kind == PlatformKind::Brick);
+        if is_brick && let Some(brick) = terrain.remove(index) {
+            self.events.push(GameEvent::BrickSmashed { at: brick.rect() });
+        }
+    }
+
+    // Resolves stomps, returns true if an enemy hurt the player
+    fn resolve_damage(&mut self) -> bool {
+        let world = &mut self.levels[self.current_level].current_room_mut().world;
+        world.damage_system(&mut self.player, &mut self.events)
+    }
+
+    fn collect_pickups(&mut self) {
+        let area = self.player_rect();
+        let world = &mut self.levels[self.current_level].current_room_mut().world;
+        world.pickup_system(area, &mut self.events);
+    }
+
+    fn draw_world(&self, theme: Theme) {
+        let level = self.current_level();
+        let room = level.current_room();
+        let tileset = self.theme_art.tileset(theme);
+        let palette = theme.palette();
+        
+        for warp in &room.warps {
+            warp.draw();
+        }
+        
+        for platform in room.terrain.platforms() {
+            platform.draw(tileset);
+        }
+        
+        // Draw goal flag
+        if level.in_main_room() {
+            level.draw_goal();
+        }
+        
+        if self.save.settings.particles {
+            self.particles.draw();
+        }
+        
+        // The player is the last thing the world draws
+        room.world.draw(&self.sprites, &palette);
+        if self.save.settings.popups {
+            self.popups.draw();
+        }
+    }
+
+    // Draws the background, plus the level when `world` is set, into the
+    // low-resolution canvas and upscales it into the window
+    fn draw_backdrop(&self, viewport: &Viewport, world: bool) {
+        // Parallax scenery sits behind everything, menus get the plain sky
+        let (theme, camera_x, camera_y) = if world {
+            (self.current_level().current_theme(), self.camera.x, self.camera.y)
+        } else {
+            (Theme::Overworld, 0.0, 0.0)
+        };
+        set_camera(&self.canvas.camera(0.0, 0.0));
+        self.theme_art.draw_background(theme, camera_x, camera_y);
+        if world {
+            self.camera.apply(&self.canvas);
+            self.draw_world(theme);
+        }
+        self.canvas.present(viewport);
+    }
+
+    fn draw_hud(&self, viewport: &Viewport) {
+        viewport.text(&format!("Score: {}", self.player.score), Anchor::TopLeft, 10.0, 30.0, 30.0, WHITE);
+        viewport.text(&format!("Lives: {}", self.player.lives), Anchor::TopLeft, 10.0, 60.0, 30.0, WHITE);
+        draw_coin_icon(viewport.anchor(Anchor::Top, -40.0, 14.0), viewport.scale);
+        viewport.text(&format!("x {:02}", self.player.coins % 100), Anchor::Top, 10.0, 30.0, 30.0, WHITE);
+        viewport.text(&format!("Level: {}", self.current_level + 1), Anchor::TopRight, -10.0, 30.0, 30.0, WHITE);
+        viewport.text("ESC: Pause", Anchor::BottomRight, -10.0, -10.0, 20.0, WHITE);
+    }
+}
+
+// What a scene asks the stack to do after its update
+enum SceneChange {
+    None,
+    Push(Box<dyn Scene>), // Overlay on top, e.g. the pause menu
+    Pop,
+    Replace(Box<dyn Scene>, TransitionKind), // Swap the top scene behind a transition
+    Reset(Box<dyn Scene>, TransitionKind),   // Clear the stack down to one scene behind a transition
+    Quit,
+}
+
+// One screen of the game. Only the top scene is updated; scenes below a
+// transparent one keep drawing underneath it
+trait Scene {
+    // Runs once the scene is on top of the stack, behind a transition if there is one
+    fn enter(&mut self, _game: &mut Game) {}
+
+    fn update(&mut self, game: &mut Game, dt: f32) -> SceneChange;
+
+    // `active` is false while another scene sits on top
+    fn draw(&self, game: &Game, viewport: &Viewport, active: bool);
+
+    fn transparent(&self) -> bool {
+        false
+    }
+
+    // Called after the display hotkeys change settings a scene might show
+    fn settings_changed(&mut self, _game: &Game) {}
+
+    // Point iris wipes close on, in virtual screen coordinates
+    fn focus(&self, _game: &Game) -> Vec2 {
+        vec2(VIRTUAL_WIDTH / 2.0, VIRTUAL_HEIGHT / 2.0)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TransitionKind {
+    Fade,
+    Iris, // Circle closing on a point, like the end of a Mario level
+    Wipe, // Black sweeps in from the left and leaves to the right
+}
+
+// Covers the screen, hands back its pending change while the screen is
+// fully covered, then uncovers it again
+struct Transition<T> {
+    kind: TransitionKind,
+    time: f32,
+    pending: Option<T>,
+}
+
+impl TransitionKind {
+    // Seconds to cover the screen, and again to uncover it
+    fn half_time(self) -> f32 {
+        match self {
+            TransitionKind::Fade => 0.3,
+            TransitionKind::Iris => 0.5,
+            TransitionKind::Wipe => 0.35,
+        }
+    }
+}
+
+impl<T> Transition<T> {
+    fn new(kind: TransitionKind, pending: T) -> Self {
+        Transition {
+            kind,
+            time: 0.0,
+            pending: Some(pending),
+        }
+    }
+
+    // Returns the pending change once, at the moment the screen is covered
+    fn update(&mut self, dt: f32) -> Option<T> {
+        self.time += dt;
+        if self.time >= self.kind.half_time() {
+            self.pending.take()
+        } else {
+            None
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.time >= self.kind.half_time() * 2.0
+    }
+
+    // 0 for an open screen, 1 when fully covered
+    fn coverage(&self) -> f32 {
+        let half = self.kind.half_time();
+        (1.0 - (self.time - half).abs() / half).clamp(0.0, 1.0)
+    }
+
+    fn draw(&self, viewport: &Viewport, focus: Vec2) {
+        let coverage = self.coverage();
+        let covering = self.time < self.kind.half_time();
+        set_camera(&viewport.screen_camera());
+        match self.kind {
+            TransitionKind::Fade => {
+                draw_rectangle(0.0, 0.0, VIRTUAL_WIDTH, VIRTUAL_HEIGHT, Color::new(0.0, 0.0, 0.0, coverage));
+            }
+            TransitionKind::Iris => {
+                // Black ring from the iris edge out past every corner of the screen
+                let outer = VIRTUAL_WIDTH + VIRTUAL_HEIGHT;
+                let radius = outer * (1.0 - coverage).powi(2);
+                let segments = 48;
+                for segment in 0..segments {
+                    let start = segment as f32 / segments as f32 * std::f32::consts::TAU;
+                    let end = (segment + 1) as f32 / segments as f32 * std::f32::consts::TAU;
+                    let (start, end) = (vec2(start.cos(), start.sin()), vec2(end.cos(), end.sin()));
+                    let inner_start = focus + start * radius;
+                    let inner_end = focus + end * radius;
+                    let outer_start = focus + start * outer;
+                    let outer_end = focus + end * outer;
+                    draw_triangle(inner_start, outer_start, outer_end, BLACK);
+                    draw_triangle(inner_start, outer_end, inner_end, BLACK);
+                }
+            }
+            TransitionKind::Wipe => {
+                let width = VIRTUAL_WIDTH * coverage;
+                let x = if covering { 0.0 } else { VIRTUAL_WIDTH - width };
+                draw_rectangle(x, 0.0, width, VIRTUAL_HEIGHT, BLACK);
+            }
+        }
+        set_default_camera();
+    }
+}
+
+struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+    transition: Option<Transition<SceneChange>>,
+}
+
+impl SceneStack {
+    fn new(scene: Box<dyn Scene>) -> Self {
+        SceneStack {
+            scenes: vec![scene],
+            transition: None,
+        }
+    }
+
+    // Returns false once the game should quit
+    fn update(&mut self, game: &mut Game, dt: f32) -> bool {
+        game.toasts.update(dt);
+        if game.handle_display_keys() {
+            for scene in &mut self.scenes {
+                scene.settings_changed(game);
+            }
+        }
+
+        // Scenes are frozen while a transition plays
+        if let Some(transition) = &mut self.transition {
+            let change = transition.update(dt);
+            if transition.finished() {
+                self.transition = None;
+            }
+            if let Some(change) = change {
+                self.apply(change, game);
+            }
+            return true;
+        }
+
+        let Some(scene) = self.scenes.last_mut() else {
+            return false;
+        };
+        match scene.update(game, dt) {
+            SceneChange::None => {}
+            SceneChange::Quit => return false,
+            change @ (SceneChange::Replace(_, kind) | SceneChange::Reset(_, kind)) => {
+                self.transition = Some(Transition::new(kind, change));
+            }
+            change => self.apply(change, game),
+        }
+        true
+    }
+
+    fn apply(&mut self, change: SceneChange, game: &mut Game) {
+        let entered = match change {
+            SceneChange::Push(scene) => Some(scene),
+            SceneChange::Pop => {
+                self.scenes.pop();
+                None
+            }
+            SceneChange::Replace(scene, _) => {
+                self.scenes.pop();
+                Some(scene)
+            }
+            SceneChange::Reset(scene, _) => {
+                self.scenes.clear();
+                Some(scene)
+            }
+            SceneChange::None | SceneChange::Quit => None,
+        };
+        if let Some(mut scene) = entered {
+            scene.enter(game);
+            self.scenes.push(scene);
+        }
+    }
+
+    fn draw(&self, game: &Game) {
+        let viewport = Viewport::current(game.save.settings.scale_mode());
+        // Letterbox bars around the upscaled frame
+        set_default_camera();
+        clear_background(BLACK);
+
+        // Start from the highest scene that hides everything below it
+        let bottom = self.scenes.iter().rposition(|scene| !scene.transparent()).unwrap_or(0);
+        let top = self.scenes.len().saturating_sub(1);
+        for (index, scene) in self.scenes.iter().enumerate().skip(bottom) {
+            scene.draw(game, &viewport, index == top);
+        }
+
+        if let Some(transition) = &self.transition &&
+           let Some(scene) = self.scenes.last() {
+            transition.draw(&viewport, scene.focus(game));
+        }
+        game.toasts.draw(&viewport);
+    }
+}
+
+// Any screen built from a Menu: the main menu and its pages, or the pause
+// menu and the options opened from it
+struct MenuScene {
+    screen: MenuScreen,
+    menu: Menu,
+    overlay: bool, // Drawn over a level in play instead of the title background
+}
+
+impl MenuScene {
+    fn new(screen: MenuScreen, overlay: bool, game: &Game) -> Self {
+        MenuScene {
+            screen,
+            menu: screen.build(game),
+            overlay,
+        }
+    }
+
+    fn main(game: &Game) -> Box<dyn Scene> {
+        Box::new(MenuScene::new(MenuScreen::Main, false, game))
+    }
+
+    fn pause(game: &Game) -> Box<dyn Scene> {
+        Box::new(MenuScene::new(MenuScreen::Pause, true, game))
+    }
+
+    fn rebuild(&mut self, game: &Game) {
+        let selected = self.menu.selected;
+        self.menu = self.screen.build(game);
+        self.menu.selected = selected;
+    }
+
+    fn handle(&mut self, event: MenuEvent, game: &mut Game) -> SceneChange {
+        match event {
+            // The main menu is the bottom of the stack, there's nothing to go back to
+            MenuEvent::Back | MenuEvent::Pressed(Command::Back) => {
+                if self.screen == MenuScreen::Main {
+                    SceneChange::None
+                } else {
+                    SceneChange::Pop
+                }
+            }
+            MenuEvent::Pressed(command) => match command {
+                Command::NewGame => SceneChange::Reset(Gameplay::run(Progress::new(0)), TransitionKind::Iris),
+                Command::Continue => match game.save.progress {
+                    Some(progress) => SceneChange::Reset(Gameplay::run(progress), TransitionKind::Iris),
+                    None => SceneChange::None,
+                },
+                Command::StartLevel(level) => SceneChange::Reset(Gameplay::run(Progress::new(level)), TransitionKind::Iris),
+                Command::Open(screen) => SceneChange::Push(Box::new(MenuScene::new(screen, self.overlay, game))),
+                Command::Quit => SceneChange::Quit,
+                Command::Resume => SceneChange::Pop,
+                Command::RestartLevel => {
+                    SceneChange::Reset(Box::new(Gameplay { start: Some(LevelStart::Restart) }), TransitionKind::Iris)
+                }
+                Command::QuitToMenu => {
+                    // Keeps the play time and distance since the last save
+                    game.save.save();
+                    SceneChange::Reset(MenuScene::main(game), TransitionKind::Fade)
+                }
+                Command::ResetBindings => {
+                    game.save.settings.bindings = KeyBindings::standard();
+                    game.apply_settings();
+                    game.save.save();
+                    self.rebuild(game);
+                    SceneChange::None
+                }
+                _ => SceneChange::None,
+            },
+            MenuEvent::Changed(command, widget) => {
+                let settings = &mut game.save.settings;
+                match (command, widget) {
+                    (Command::MusicVolume, Widget::Slider { value, .. }) => settings.music_volume = value,
+                    (Command::SoundVolume, Widget::Slider { value, .. }) => settings.sound_volume = value,
+                    (Command::Fullscreen, Widget::Toggle(on)) => settings.fullscreen = on,
+                    (Command::IntegerScaling, Widget::Toggle(on)) => settings.integer_scaling = on,
+                    (Command::Bind(action), Widget::Binding(key)) => settings.bindings.set(action, key),
+                    (Command::GameSpeed, Widget::Slider { value, .. }) => settings.game_speed = 50 + value * 10,
+                    (Command::Particles, Widget::Toggle(on)) => settings.particles = on,
+                    (Command::Popups, Widget::Toggle(on)) => settings.popups = on,
+                    _ => {}
+                }
+                game.apply_settings();
+                game.save.save();
+                // A swapped binding changes another row too
+                if matches!(command, Command::Bind(_)) {
+                    self.rebuild(game);
+                }
+                SceneChange::None
+            }
+        }
+    }
+}
+
+impl Scene for MenuScene {
+    fn update(&mut self, game: &mut Game, _dt: f32) -> SceneChange {
+        // Start resumes from the pause menu, Escape is already handled as Back
+        if self.screen == MenuScreen::Pause && game.input.gamepad.pressed(PadButton::Start) {
+            return SceneChange::Pop;
+        }
+        match self.menu.update(&game.input) {
+            Some(event) => self.handle(event, game),
+            None => SceneChange::None,
+        }
+    }
+
+    fn draw(&self, game: &Game, viewport: &Viewport, active: bool) {
+        // Menus left underneath another menu stay hidden
+        if !active {
+            return;
+        }
+        if self.overlay {
+            set_camera(&viewport.screen_camera());
+            draw_rectangle(0.0, 0.0, VIRTUAL_WIDTH, VIRTUAL_HEIGHT, Color::new(0.0, 0.0, 0.0, 0.5));
+            set_default_camera();
+        } else {
+            game.draw_backdrop(viewport, false);
+        }
+        self.menu.draw(viewport);
+    }
+
+    fn transparent(&self) -> bool {
+        self.overlay
+    }
+
+    fn settings_changed(&mut self, game: &Game) {
+        self.rebuild(game);
+    }
+}
+
+// How a Gameplay scene sets the level up when it's entered
+enum LevelStart {
+    Run(Progress),
+    Restart,
+    Next,
+    Respawn, // Back after losing a life
+    Continue,
+}
+
+// The level being played, with the HUD on top
+struct Gameplay {
+    start: Option<LevelStart>,
+}
+
+impl Gameplay {
+    fn run(progress: Progress) -> Box<dyn Scene> {
+        Box::new(Gameplay { start: Some(LevelStart::Run(progress)) })
+    }
+}
+
+impl Scene for Gameplay {
+    fn enter(&mut self, game: &mut Game) {
+        match self.start.take() {
+            Some(LevelStart::Run(progress)) => game.start_level(progress),
+            Some(LevelStart::Restart) => game.restart_level(),
+            Some(LevelStart::Next) => game.next_level(),
+            Some(LevelStart::Respawn) => game.retry_level(),
+            Some(LevelStart::Continue) => game.continue_level(),
+            None => {}
+        }
+    }
+
+    fn update(&mut self, game: &mut Game, dt: f32) -> SceneChange {
+        // macroquad 0.4 doesn't pass on focus or minimize events, but a
+        // minimized, hidden or dragged window stops producing frames, so a
+        // long gap stands in for the window having been in the background
+        if dt > SUSPEND_TIME || game.input.pause_pressed() {
+            return SceneChange::Push(MenuScene::pause(game));
+        }
+
+        game.update_level(dt);
+        game.dispatch_events();
+        if game.death_finished() {
+            if game.player.lives <= 0 {
+                game.game_over();
+                if game.continues_left() > 0 {
+                    return SceneChange::Replace(Box::new(ContinueScreen { time: 0.0 }), TransitionKind::Fade);
+                }
+                return SceneChange::Replace(Box::new(GameOver), TransitionKind::Fade);
+            }
+            return SceneChange::Replace(Box::new(LivesRemaining { time: 0.0 }), TransitionKind::Fade);
+        }
+        if game.level_completed {
+            return SceneChange::Replace(Box::new(LevelComplete), TransitionKind::Iris);
+        }
+        SceneChange::None
+    }
+
+    fn draw(&self, game: &Game, viewport: &Viewport, active: bool) {
+        game.draw_backdrop(viewport, true);
+        if let Some(transition) = &game.transition {
+            transition.draw(viewport, game.player_focus());
+        }
+        // The pause menu hides the HUD
+        if active {
+            game.draw_hud(viewport);
+        }
+    }
+
+    fn focus(&self, game: &Game) -> Vec2 {
+        game.player_focus()
+    }
+}
+
+// Interstitial after a death showing how many lives are left
+struct LivesRemaining {
+    time: f32,
+}
+
+impl Scene for LivesRemaining {
+    fn update(&mut self, game: &mut Game, dt: f32) -> SceneChange {
+        self.time += dt;
+        if self.time < LIVES_SCREEN_TIME && !game.input.menu_pressed(MenuInput::Confirm) {
+            return SceneChange::None;
+        }
+        SceneChange::Replace(Box::new(Gameplay { start: Some(LevelStart::Respawn) }), TransitionKind::Iris)
+    }
+
+    fn draw(&self, game: &Game, viewport: &Viewport, _active: bool) {
+        viewport.text(&format!("LEVEL {}", game.current_level + 1), Anchor::Center, 0.0, -40.0, 50.0, WHITE);
+        let lives = if game.player.lives == 1 {
+            "1 life remaining".to_string()
+        } else {
+            format!("{} lives remaining", game.player.lives)
+        };
+        viewport.text(&lives, Anchor::Center, 0.0, 20.0, 30.0, WHITE);
+    }
+}
+
+// Results screen between levels
+struct LevelComplete;
+
+impl Scene for LevelComplete {
+    fn update(&mut self, game: &mut Game, _dt: f32) -> SceneChange {
+        if !game.input.menu_pressed(MenuInput::Confirm) {
+            return SceneChange::None;
+        }
+        if game.current_level + 1 < game.levels.len() {
+            SceneChange::Replace(Box::new(Gameplay { start: Some(LevelStart::Next) }), TransitionKind::Iris)
+        } else {
+            // Game completed, level_completed stays set for the victory screen
+            game.game_over();
+            SceneChange::Replace(Box::new(GameOver), TransitionKind::Fade)
+        }
+    }
+
+    fn draw(&self, game: &Game, viewport: &Viewport, _active: bool) {
+        game.draw_backdrop(viewport, false);
+        let (collected, total) = game.current_level().coin_count();
+        viewport.text(&format!("LEVEL {} COMPLETE!", game.current_level + 1), Anchor::Center, 0.0, -80.0, 50.0, GOLD);
+        draw_coin_icon(viewport.anchor(Anchor::Center, -90.0, -48.0), viewport.scale);
+        viewport.text(&format!("Coins: {} / {}", collected, total), Anchor::Center, 10.0, -30.0, 30.0, WHITE);
+        viewport.text(&format!("Score: {}", game.player.score), Anchor::Center, 0.0, 5.0, 30.0, WHITE);
+        let par_time = game.current_level().par_time;
+        let time_color = if game.level_time <= par_time { YELLOW } else { WHITE };
+        let time = format!("Time: {}  Par: {}", format_time(game.level_time), format_time(par_time));
+        viewport.text(&time, Anchor::Center, 0.0, 40.0, 30.0, time_color);
+        if collected == total {
+            viewport.text("Every coin collected!", Anchor::Center, 0.0, 75.0, 25.0, YELLOW);
+        }
+        viewport.text("Press SPACE or ENTER to continue", Anchor::Center, 0.0, 120.0, 30.0, WHITE);
+    }
+}
+
+// Out of lives: a countdown to continue from the start of the level
+struct ContinueScreen {
+    time: f32,
+}
+
+impl Scene for ContinueScreen {
+    fn update(&mut self, game: &mut Game, dt: f32) -> SceneChange {
+        self.time += dt;
+        if game.input.menu_pressed(MenuInput::Confirm) {
+            return SceneChange::Replace(Box::new(Gameplay { start: Some(LevelStart::Continue) }), TransitionKind::Iris);
+        }
+        if self.time >= CONTINUE_TIME || game.input.menu_pressed(MenuInput::Back) {
+            return SceneChange::Replace(Box::new(GameOver), TransitionKind::Fade);
+        }
+        SceneChange::None
+    }
+
+    fn draw(&self, game: &Game, viewport: &Viewport, _active: bool) {
+        let countdown = (CONTINUE_TIME - self.time).ceil().max(0.0);
+        viewport.text("CONTINUE?", Anchor::Center, 0.0, -100.0, 60.0, GOLD);
+        viewport.text(&format!("{}", countdown), Anchor::Center, 0.0, -10.0, 80.0, WHITE);
+        viewport.text(
+            &format!("Restart level {} with a new score", game.current_level + 1),
+            Anchor::Center, 0.0, 50.0, 30.0, WHITE,
+        );
+        viewport.text(&format!("Continues left: {}", game.continues_left()), Anchor::Center, 0.0, 90.0, 25.0, GRAY);
+        viewport.text("ENTER: Continue   ESC: Give up", Anchor::Center, 0.0, 150.0, 25.0, WHITE);
+    }
+}
+
+// Minutes and seconds, e.g. 3:07
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as i32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// Run summary, or the victory screen once every level is done
+struct GameOver;
+
+impl Scene for GameOver {
+    fn update(&mut self, game: &mut Game, _dt: f32) -> SceneChange {
+        if game.input.menu_pressed(MenuInput::Confirm) || game.input.menu_pressed(MenuInput::Back) {
+            SceneChange::Reset(MenuScene::main(game), TransitionKind::Fade)
+        } else {
+            SceneChange::None
+        }
+    }
+
+    fn draw(&self, game: &Game, viewport: &Viewport, _active: bool) {
+        game.draw_backdrop(viewport, false);
+        if game.level_completed {
+            viewport.text("CONGRATULATIONS!", Anchor::Center, 0.0, -200.0, 50.0, GOLD);
+            viewport.text("YOU COMPLETED ALL LEVELS!", Anchor::Center, 0.0, -150.0, 30.0, WHITE);
+        } else {
+            viewport.text("GAME OVER", Anchor::Center, 0.0, -180.0, 60.0, RED);
+        }
+        viewport.text(&format!("Final Score: {}", game.player.score), Anchor::Center, 0.0, -90.0, 40.0, WHITE);
+
+        let run = &game.run;
+        let stats = [
+            ("Levels Cleared", run.levels_cleared.to_string()),
+            ("Coins", run.coins.to_string()),
+            ("Enemies Stomped", run.stomps.to_string()),
+            ("Deaths", run.deaths.to_string()),
+            ("Continues Used", run.continues_used.to_string()),
+            ("Time", format_time(run.time)),
+        ];
+        for (row, (label, value)) in stats.iter().enumerate() {
+            let y = -40.0 + row as f32 * 32.0;
+            viewport.text(label, Anchor::Center, -100.0, y, 25.0, GRAY);
+            viewport.text(value, Anchor::Center, 120.0, y, 25.0, WHITE);
+        }
+        viewport.text("Press ENTER for main menu", Anchor::Center, 0.0, 190.0, 30.0, WHITE);
+    }
+}
+
+// Heatmap export for level tuning: cargo run -- --heatmap LEVEL [OUTPUT.png|OUTPUT.csv]
+const HEATMAP_SCALE: f32 = 0.5;    // Image pixels per world pixel
+const HEATMAP_RADIUS: f32 = 48.0;  // World pixels each death spreads over
+const HEATMAP_CELL: f32 = 32.0;    // World pixels per CSV cell
+
+// One line of the death log
+struct DeathRecord {
+    level: usize, // Index, so 1-3 is 2
+    room: usize,
+    x: f32,
+    y: f32,
+    cause: DeathCause,
+}
+
+impl DeathRecord {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"level\":{},\"room\":{},\"x\":{:.1},\"y\":{:.1},\"cause\":\"{}\"}}",
+            self.level, self.room, self.x, self.y, self.cause.key()
+        )
+    }
+
+    // Reads back lines written by to_json, skipping anything else
+    fn parse(line: &str) -> Option<DeathRecord> {
+        Some(DeathRecord {
+            level: json_field(line, "level")?.parse().ok()?,
+            room: json_field(line, "room")?.parse().ok()?,
+            x: json_field(line, "x")?.parse().ok()?,
+            y: json_field(line, "y")?.parse().ok()?,
+            cause: DeathCause::parse(json_field(line, "cause")?)?,
+        })
+    }
+}
+
+// Value of a flat JSON object's field, without quotes
+fn json_field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let pattern = format!("\"{}\":", key);
+    let rest = line[line.find(&pattern)? + pattern.len()..].trim_start();
+    let end = rest.find([',', '}']).unwrap_or(rest.len());
+    Some(rest[..end].trim().trim_matches('"'))
+}
+
+fn export_heatmap(args: &[String]) {
+    let level = args.first()
+        .and_then(|arg| arg.parse::<usize>().ok())
+        .filter(|level| (1..=LEVEL_COUNT).contains(level));
+    let Some(level) = level else {
+        println!("Usage: slack_game --heatmap LEVEL [OUTPUT.png|OUTPUT.csv]");
+        return;
+    };
+    let output = args.get(1).cloned().unwrap_or(format!("deaths_level_{}.png", level));
+    let log = std::fs::read_to_string(DEATH_LOG_PATH).unwrap_or_default();
+    // Only the main room is drawn; bonus rooms have their own layouts
+    let deaths: Vec<DeathRecord> = log.lines()
+        .filter_map(DeathRecord::parse)
+        .filter(|death| death.level == level - 1 && death.room == 0)
+        .collect();
+
+    if output.ends_with(".csv") {
+        if let Err(error) = std::fs::write(&output, heatmap_csv(&deaths)) {
+            println!("Could not write {}: {}", output, error);
+            return;
+        }
+    } else {
+        if !output.ends_with(".png") {
+            println!("Heatmaps are written as .png or .csv, not {}", output);
+            return;
+        }
+        // export_png panics on a path it can't write, so make sure it can first
+        if let Err(error) = std::fs::File::create(&output) {
+            println!("Could not write {}: {}", output, error);
+            return;
+        }
+        heatmap_image(&Level::create(level - 1), &deaths).export_png(&output);
+    }
+    println!("Wrote {} deaths on level {} to {}", deaths.len(), level, output);
+}
+
+// Death counts per grid cell, worst first
+fn heatmap_csv(deaths: &[DeathRecord]) -> String {
+    let mut cells: HashMap<(i32, i32), [i32; DeathCause::ALL.len()]> = HashMap::new();
+    for death in deaths {
+        let cell = ((death.x / HEATMAP_CELL).floor() as i32, (death.y / HEATMAP_CELL).floor() as i32);
+        cells.entry(cell).or_default()[death.cause as usize] += 1;
+    }
+    let mut rows: Vec<((i32, i32), [i32; DeathCause::ALL.len()])> = cells.into_iter().collect();
+    rows.sort_by_key(|&((x, y), causes)| (-causes.iter().sum::<i32>(), x, y));
+
+    let causes: Vec<&str> = DeathCause::ALL.iter().map(|cause| cause.key()).collect();
+    let mut csv = format!("x,y,deaths,{}\n", causes.join(","));
+    for ((x, y), counts) in rows {
+        let total: i32 = counts.iter().sum();
+        let counts: Vec<String> = counts.iter().map(|count| count.to_string()).collect();
+        csv += &format!("{},{},{},{}\n", x as f32 * HEATMAP_CELL, y as f32 * HEATMAP_CELL, total, counts.join(","));
+    }
+    csv
+}
+
+// Main room platforms and goal with the deaths glowing over them
+fn heatmap_image(level: &Level, deaths: &[DeathRecord]) -> Image {
+    let bounds = level.rooms[0].bounds;
+    let width = (bounds.w * HEATMAP_SCALE) as usize;
+    let height = (bounds.h * HEATMAP_SCALE) as usize;
+    let mut pixels = vec![Color::new(0.1, 0.1, 0.15, 1.0); width * height];
+    let mut fill = |area: Rect, color: Color| {
+        let left = ((area.x - bounds.x) * HEATMAP_SCALE).max(0.0) as usize;
+        let top = ((area.y - bounds.y) * HEATMAP_SCALE).max(0.0) as usize;
+        let right = (((area.x + area.w - bounds.x) * HEATMAP_SCALE) as usize).min(width);
+        let bottom = (((area.y + area.h - bounds.y) * HEATMAP_SCALE) as usize).min(height);
+        for y in top..bottom {
+            for x in left..right {
+                pixels[y * width + x] = color;
+            }
+        }
+    };
+    for platform in level.rooms[0].terrain.platforms() {
+        let color = match platform.kind {
+            PlatformKind::Ground => GRAY,
+            PlatformKind::Pipe => DARKGREEN,
+            PlatformKind::Brick => BROWN,
+        };
+        fill(platform.rect(), color);
+    }
+    fill(Rect::new(level.goal_x, level.goal_y - 100.0, 6.0, 150.0), GOLD);
+
+    // Each death adds a cone of heat; falls below the room land on its bottom edge
+    let mut heat = vec![0.0f32; width * height];
+    let radius = HEATMAP_RADIUS * HEATMAP_SCALE;
+    for death in deaths {
+        let center_x = (death.x - bounds.x) * HEATMAP_SCALE;
+        let center_y = ((death.y - bounds.y) * HEATMAP_SCALE).clamp(0.0, height as f32 - 1.0);
+        let top = (center_y - radius).max(0.0) as usize;
+        let bottom = ((center_y + radius) as usize + 1).min(height);
+        let left = (center_x - radius).max(0.0) as usize;
+        let right = ((center_x + radius) as usize + 1).min(width);
+        for y in top..bottom {
+            for x in left..right {
+                let distance = vec2(x as f32 - center_x, y as f32 - center_y).length();
+                heat[y * width + x] += (1.0 - distance / radius).max(0.0);
+            }
+        }
+    }
+
+    let hottest = heat.iter().copied().fold(0.0f32, f32::max).max(1.0);
+    let ramp = [BLUE, YELLOW, RED];
+    let mut image = Image::gen_image_color(width as u16, height as u16, BLACK);
+    for y in 0..height {
+        for x in 0..width {
+            let amount = heat[y * width + x] / hottest;
+            let base = pixels[y * width + x];
+            let glow = sample_ramp(&ramp, amount);
+            let alpha = if amount > 0.0 { 0.3 + amount * 0.6 } else { 0.0 };
+            let color = Color::new(
+                base.r + (glow.r - base.r) * alpha,
+                base.g + (glow.g - base.g) * alpha,
+                base.b + (glow.b - base.b) * alpha,
+                1.0,
+            );
+            // export_png flips rows, so images are stored bottom up
+            image.set_pixel(x as u32, (height - 1 - y) as u32, color);
+        }
+    }
+    image
+}
+
+// Broadphase benchmark: cargo run --release -- --bench-broadphase
+const BENCH_TICKS: usize = 1200; // Ten seconds at 120 Hz
+const BENCH_COLUMNS: usize = 1000; // Each a ground block with three platforms above
+
+// A long synthetic room, far bigger than any real level
+fn bench_room(cell_size: f32) -> Room {
+    let mut platforms = Vec::new();
+    let mut coins = Vec::new();
+    let mut enemies = Vec::new();
+    for column in 0..BENCH_COLUMNS {
+        let x = column as f32 * 200.0;
+        platforms.push(Platform::new(x, 550.0, 200.0, 50.0));
+        for (layer, y) in [450.0, 350.0, 250.0].into_iter().enumerate() {
+            platforms.push(Platform::new(x + 40.0 * layer as f32, y, 80.0, 20.0));
+        }
+        coins.push(Bundle::coin(x + 60.0, 420.0));
+        coins.push(Bundle::coin(x + 140.0, 420.0));
+        if column % 2 == 1 {
+            enemies.push(Bundle::enemy(x + 100.0, 518.0));
+        }
+    }
+    Room {
+        terrain: Terrain::with_cell_size(platforms, cell_size),
+        world: World::with_cell_size(enemies.into_iter().chain(coins), cell_size),
+        warps: Vec::new(),
+        theme: None,
+        music: Music::Overworld,
+        bounds: Rect::new(0.0, 0.0, BENCH_COLUMNS as f32 * 200.0, 600.0),
+        camera_zones: Vec::new(),
+    }
+}
+
+// Runs the collision work of update_level on a huge room, with the grid
+// and with a single cell that makes every check visit everything. Hostiles
+// aren't in a grid either way, so the damage checks are timed on their own
+fn bench_broadphase() {
+    let dt = 1.0 / 120.0;
+    let budget = dt * 1000.0;
+    let room = bench_room(GRID_CELL_SIZE);
+    println!(
+        "{} platforms, {} enemies, {} coins, {} ticks",
+        room.terrain.platforms.len(), room.world.hostile_entities.len(), room.world.coins, BENCH_TICKS
+    );
+    for (name, cell_size) in [("grid", GRID_CELL_SIZE), ("brute force", f32::MAX)] {
+        let mut room = bench_room(cell_size);
+        room.world.insert(PLAYER, Bundle::player(100.0, 480.0));
+        let mut player = Player::new();
+        let mut events = Vec::new();
+        let mut hurts = 0;
+        let mut total = 0.0;
+        let mut worst: f32 = 0.0;
+        let mut damage = 0.0;
+        for _ in 0..BENCH_TICKS {
+            let start = std::time::Instant::now();
+            // Runs right and jumps whenever it lands
+            let on_ground = room.world.on_ground(PLAYER);
+            if let Some(velocity) = &mut room.world.velocities[PLAYER] {
+                velocity.x = 200.0;
+                if on_ground {
+                    velocity.y = -500.0;
+                }
+            }
+            room.world.update(dt, &room.terrain);
+            let damage_start = std::time::Instant::now();
+            // Getting hurt doesn't end the run here, so every tick does the same work
+            if room.world.damage_system(&mut player, &mut events) {
+                hurts += 1;
+            }
+            damage += damage_start.elapsed().as_secs_f32() * 1000.0;
+            let area = room.world.rect(PLAYER).unwrap_or_default();
+            room.world.pickup_system(area, &mut events);
+            let elapsed = start.elapsed().as_secs_f32() * 1000.0;
+            total += elapsed;
+            worst = worst.max(elapsed);
+        }
+        let average = total / BENCH_TICKS as f32;
+        let collected = events.iter().filter(|event| matches!(event, GameEvent::CoinCollected { .. })).count();
+        let stomped = events.iter().filter(|event| matches!(event, GameEvent::EnemyStomped { .. })).count();
+        println!(
+            "{:<12} average {:.3} ms, worst {:.3} ms per tick ({:.1}% of the {:.2} ms budget at 120 Hz), \
+             damage checks {:.4} ms; {} coins, {} stomps, {} hurts",
+            name, average, worst, average / budget * 100.0, budget, damage / BENCH_TICKS as f32, collected, stomped, hurts
+        );
+    }
+}
+
+fn window_conf() -> Conf {
+    Conf {
+        window_title: "Slack Game".to_string(),
+        window_width: VIRTUAL_WIDTH as i32,
+        window_height: VIRTUAL_HEIGHT as i32,
+        window_resizable: true,
+        ..Default::default()
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("--heatmap") => return export_heatmap(&args[1..]),
+        Some("--bench-broadphase") => return bench_broadphase(),
+        _ => {}
+    }
+    macroquad::Window::from_config(window_conf(), run());
+}
+
+async fn run() {
+    let sprites = Sprites::load("assets/sprites").await;
+    let mut game = Game::new(sprites);
+    let mut scenes = SceneStack::new(MenuScene::main(&game));
+    
+    loop {
+        let dt = get_frame_time();
+        
+        game.input.poll();
+        if !scenes.update(&mut game, dt) {
+            break;
+        }
+        scenes.draw(&game);
+
+        next_frame().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_a_taken_key_swaps_the_two_actions() {
+        let mut bindings = KeyBindings::standard();
+
+        bindings.set(Action::Jump, KeyCode::A);
+
+        assert_eq!(bindings.key(Action::Jump), KeyCode::A);
+        assert_eq!(bindings.key(Action::Left), KeyCode::Space);
+        bindings.set(Action::Right, KeyCode::K);
+        assert_eq!(bindings.keys, [KeyCode::Space, KeyCode::K, KeyCode::W, KeyCode::S, KeyCode::A]);
+    }
+
+    // A 32px player whose top-left corner is at (x, y), facing right
+    fn view(x: f32, y: f32, on_ground: bool) -> PlayerView {
+        PlayerView { rect: Rect::new(x, y, 32.0, 32.0), facing_right: true, on_ground }
+    }
+
+    #[test]
+    fn camera_stays_inside_level_bounds() {
+        let bounds = Rect::new(0.0, -200.0, 1900.0, 800.0);
+        let mut camera = Camera::new();
+
+        camera.x = -100.0;
+        camera.y = 500.0;
+        camera.clamp_to(bounds);
+        assert_eq!((camera.x, camera.y), (0.0, 0.0));
+
+        camera.x = 5000.0;
+        camera.y = -900.0;
+        camera.clamp_to(bounds);
+        assert_eq!((camera.x, camera.y), (1100.0, -200.0));
+    }
+
+    #[test]
+    fn camera_centres_on_rooms_smaller_than_the_screen() {
+        let mut camera = Camera::new();
+
+        camera.clamp_to(Rect::new(0.0, 0.0, 400.0, 300.0));
+
+        assert_eq!((camera.x, camera.y), (-200.0, -150.0));
+    }
+
+    #[test]
+    fn camera_scrolls_only_past_the_dead_zone() {
+        let settings = CameraSettings::standard();
+        let bounds = Rect::new(-10000.0, -10000.0, 20000.0, 20000.0);
+        let mut camera = Camera::new();
+
+        // Centre at 420, inside the 80px dead zone around 400
+        camera.update(0.1, view(404.0, 284.0, true), &settings, bounds, None);
+        assert_eq!(camera.focus_x, 400.0);
+
+        // Centre at 500 drags the zone's right edge along
+        camera.update(0.1, view(484.0, 284.0, true), &settings, bounds, None);
+        assert_eq!(camera.focus_x, 460.0);
+    }
+
+    #[test]
+    fn camera_follows_vertically_only_on_landing() {
+        let settings = CameraSettings::standard();
+        let bounds = Rect::new(-10000.0, -10000.0, 20000.0, 20000.0);
+        let mut camera = Camera::new();
+
+        camera.update(0.1, view(384.0, 200.0, false), &settings, bounds, None);
+        assert_eq!(camera.focus_y, 300.0);
+
+        camera.update(0.1, view(384.0, 200.0, true), &settings, bounds, None);
+        assert_eq!(camera.focus_y, 216.0);
+    }
+
+    fn zone(area: Rect, mode: CameraMode) -> CameraZone {
+        CameraZone { area, mode }
+    }
+
+    #[test]
+    fn locked_zone_holds_the_screen() {
+        let settings = CameraSettings::standard();
+        let bounds = Rect::new(0.0, 0.0, 4000.0, 600.0);
+        let arena = zone(Rect::new(1000.0, 0.0, 800.0, 600.0), CameraMode::Locked { x: 1000.0, y: 0.0 });
+        let mut camera = Camera::new();
+
+        for _ in 0..300 {
+            camera.update(1.0 / 60.0, view(1500.0, 500.0, true), &settings, bounds, Some(&arena));
+        }
+
+        assert!((camera.x - 1000.0).abs() < 0.01);
+        assert_eq!(camera.y, 0.0);
+    }
+
+    #[test]
+    fn arena_lock_releases_once_its_enemies_are_gone() {
+        let arena = zone(Rect::new(1000.0, 0.0, 800.0, 600.0), CameraMode::Locked { x: 1000.0, y: 0.0 });
+        let mut room = Room {
+            terrain: Terrain::new(Vec::new()),
+            world: World::new(vec![Bundle::enemy(1400.0, 500.0)]),
+            warps: Vec::new(),
+            theme: None,
+            music: Music::Overworld,
+            bounds: Rect::new(0.0, 0.0, 4000.0, 600.0),
+            camera_zones: vec![arena],
+        };
+        let player = Rect::new(1100.0, 500.0, 32.0, 32.0);
+
+        assert!(room.active_camera_zone(player).is_some());
+        room.world.stomp(1);
+        assert!(room.active_camera_zone(player).is_none());
+    }
+
+    #[test]
+    fn vertical_only_zone_centres_horizontally_and_follows_jumps() {
+        let settings = CameraSettings::standard();
+        let bounds = Rect::new(0.0, -800.0, 800.0, 1400.0);
+        let tower = zone(bounds, CameraMode::VerticalOnly);
+        let mut camera = Camera::new();
+
+        camera.update(0.1, view(700.0, -300.0, false), &settings, bounds, Some(&tower));
+
+        assert_eq!(camera.x, 0.0);
+        assert_eq!(camera.focus_y, -284.0);
+    }
+
+    #[test]
+    fn auto_scroll_zone_moves_at_its_own_pace_and_stops_at_the_end() {
+        let settings = CameraSettings::standard();
+        let bounds = Rect::new(0.0, 0.0, 4000.0, 600.0);
+        let section = zone(Rect::new(0.0, 0.0, 2000.0, 600.0), CameraMode::AutoScroll { speed: 100.0 });
+        let mut camera = Camera::new();
+
+        // The player standing still doesn't hold it back
+        camera.update(0.5, view(100.0, 500.0, true), &settings, bounds, Some(&section));
+        assert_eq!(camera.x, 50.0);
+
+        for _ in 0..100 {
+            camera.update(0.5, view(100.0, 500.0, true), &settings, bounds, Some(&section));
+        }
+        assert_eq!(camera.x, 1200.0);
+    }
+
+    #[test]
+    fn screen_edge_pushes_the_player_and_crushes_them_into_walls() {
+        let mut world = World::new(Vec::new());
+        world.insert(PLAYER, Bundle::player(50.0, 500.0));
+
+        assert!(!world.confine(PLAYER, 100.0, 900.0, &Terrain::new(Vec::new())));
+        assert_eq!(world.rect(PLAYER).unwrap().x, 100.0);
+
+        let wall = Terrain::new(vec![Platform::new(140.0, 400.0, 40.0, 200.0)]);
+        assert!(world.confine(PLAYER, 120.0, 900.0, &wall));
+    }
+
+    #[test]
+    fn atlas_reads_frames_with_and_without_offsets() {
+        let frames = parse_atlas("\
+            # comment\n\
+            \n\
+            coin 0 0 16 16\n\
+            player_idle 16 0 32 32 -2 4\n");
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames["coin"].source, Rect::new(0.0, 0.0, 16.0, 16.0));
+        assert_eq!(frames["coin"].offset, Vec2::ZERO);
+        assert_eq!(frames["player_idle"].source, Rect::new(16.0, 0.0, 32.0, 32.0));
+        assert_eq!(frames["player_idle"].offset, vec2(-2.0, 4.0));
+    }
+
+    #[test]
+    fn atlas_skips_malformed_lines() {
+        let frames = parse_atlas("\
+            short 0 0 16\n\
+            words 0 0 sixteen 16\n\
+            one_offset 0 0 16 16 2\n\
+            too_long 0 0 16 16 2 2 2\n\
+            coin 0 0 16 16\n");
+
+        assert_eq!(frames.keys().collect::<Vec<_>>(), ["coin"]);
+    }
+
+    const TEST_CLIPS: &[AnimClip] = &[
+        AnimClip {
+            name: "walk",
+            frames: &[
+                AnimFrame { sprite: "walk_0", duration: 0.25 },
+                AnimFrame { sprite: "walk_1", duration: 0.25 },
+            ],
+            mode: PlayMode::Loop,
+        },
+        AnimClip {
+            name: "die",
+            frames: &[
+                AnimFrame { sprite: "die_0", duration: 0.25 },
+                AnimFrame { sprite: "die_1", duration: 0.5 },
+            ],
+            mode: PlayMode::Once,
+        },
+    ];
+
+    #[test]
+    fn looping_clips_wrap_around() {
+        let mut animator = Animator::new(TEST_CLIPS, AnimState::Walk);
+        assert_eq!(animator.sprite(), "walk_0");
+
+        animator.update(0.25);
+        assert_eq!(animator.sprite(), "walk_1");
+        animator.update(0.25);
+        assert_eq!(animator.sprite(), "walk_0");
+        // Long frames skip ahead as many frames as fit
+        animator.update(0.75);
+        assert_eq!(animator.sprite(), "walk_1");
+        assert!(!animator.finished());
+    }
+
+    #[test]
+    fn one_shot_clips_hold_their_last_frame() {
+        let mut animator = Animator::new(TEST_CLIPS, AnimState::Walk);
+        animator.set_state(AnimState::Die);
+        assert_eq!(animator.sprite(), "die_0");
+
+        animator.update(0.25);
+        assert_eq!(animator.sprite(), "die_1");
+        animator.update(0.25);
+        assert!(!animator.finished());
+        animator.update(0.25);
+        assert!(animator.finished());
+        animator.update(5.0);
+        assert_eq!(animator.sprite(), "die_1");
+    }
+
+    #[test]
+    fn only_a_new_state_restarts_the_clip() {
+        let mut animator = Animator::new(TEST_CLIPS, AnimState::Walk);
+        animator.update(0.25);
+
+        animator.set_state(AnimState::Walk);
+        assert_eq!(animator.sprite(), "walk_1");
+        // States without a clip fall back to the first one, from the top
+        animator.set_state(AnimState::Idle);
+        assert_eq!(animator.sprite(), "walk_0");
+    }
+
+    #[test]
+    fn player_has_a_clip_for_every_state() {
+        let states = [
+            AnimState::Idle, AnimState::Walk, AnimState::Run, AnimState::Jump,
+            AnimState::Fall, AnimState::Hurt, AnimState::Die,
+        ];
+        for state in states {
+            assert!(PLAYER_CLIPS.iter().any(|clip| clip.name == state.clip_name()), "{}", state.clip_name());
+        }
+    }
+
+    // A room's world with the player dropped in at (x, y)
+    fn world_with_player(bundles: Vec<Bundle>, x: f32, y: f32, velocity_y: f32) -> World {
+        let mut world = World::new(bundles);
+        world.insert(PLAYER, Bundle::player(x, y));
+        world.velocities[PLAYER] = Some(Velocity { x: 0.0, y: velocity_y });
+        world
+    }
+
+    // Hands events to Player::award the way Game::dispatch_events does,
+    // extra lives it publishes included
+    fn award_all(player: &mut Player, mut events: Vec<GameEvent>, popups: &mut Popups) {
+        while !events.is_empty() {
+            let mut published = Vec::new();
+            for event in &events {
+                player.award(event, Rect::new(0.0, 0.0, 32.0, 32.0), popups, &mut published);
+            }
+            events = published;
+        }
+    }
+
+    #[test]
+    fn stomp_chain_escalates_to_an_extra_life() {
+        let mut world = world_with_player(Vec::new(), 100.0, 80.0, 0.0);
+        let mut player = Player::new();
+        let mut popups = Popups::new(8);
+        let mut scores = Vec::new();
+
+        for _ in 0..6 {
+            // A fresh enemy under the player, who hasn't landed since the last one
+            let enemy = world.reserve();
+            world.insert(enemy, Bundle::enemy(100.0, 100.0));
+            world.velocities[PLAYER] = Some(Velocity { x: 0.0, y: 100.0 });
+            let mut events = Vec::new();
+            world.damage_system(&mut player, &mut events);
+            award_all(&mut player, events, &mut popups);
+            scores.push(player.score);
+        }
+
+        assert_eq!(scores, [100, 300, 700, 1500, 2500, 2500]);
+        assert_eq!(player.lives, STARTING_LIVES + 1);
+    }
+
+    #[test]
+    fn hundredth_coin_is_worth_an_extra_life() {
+        let coins = (0..100).map(|_| Bundle::coin(100.0, 100.0)).collect();
+        let mut world = world_with_player(coins, 100.0, 100.0, 0.0);
+        let area = world.rect(PLAYER).unwrap();
+        let mut player = Player::new();
+        let mut popups = Popups::new(8);
+
+        let mut events = Vec::new();
+        world.pickup_system(area, &mut events);
+        let last = events.pop().unwrap();
+        award_all(&mut player, events, &mut popups);
+        assert_eq!((player.coins, player.lives), (99, STARTING_LIVES));
+
+        award_all(&mut player, vec![last], &mut popups);
+        assert_eq!((player.coins, player.score, player.lives), (100, 1000, STARTING_LIVES + 1));
+    }
+
+    #[test]
+    fn popups_format_their_text_on_spawn_and_reuse_slots() {
+        let mut popups = Popups::new(2);
+
+        popups.spawn(PopupText::Points(100), 0.0, 0.0);
+        popups.spawn(PopupText::OneUp, 0.0, 0.0);
+        assert_eq!(popups.pool[0].label, "100");
+        assert_eq!(popups.pool[1].label, "1UP");
+
+        popups.spawn(PopupText::Points(50), 0.0, 0.0);
+        assert_eq!(popups.pool[0].label, "50");
+        assert_eq!(popups.pool[0].age, 0.0);
+    }
+
+    #[test]
+    fn settings_and_bindings_survive_a_save() {
+        let mut save = SaveData::new();
+        save.unlocked_levels = 3;
+        save.high_scores = vec![5000, 1200];
+        save.settings.music_volume = 0;
+        save.settings.sound_volume = 10;
+        save.settings.fullscreen = true;
+        save.settings.integer_scaling = true;
+        save.settings.game_speed = 70;
+        save.settings.particles = false;
+        save.settings.popups = false;
+        save.settings.bindings.set(Action::Jump, KeyCode::K);
+        save.settings.bindings.set(Action::Left, KeyCode::D);
+
+        let loaded = SaveData::parse(&save.to_text());
+
+        let settings = &loaded.settings;
+        assert_eq!(loaded.unlocked_levels, 3);
+        assert_eq!(loaded.high_scores, [5000, 1200]);
+        assert_eq!((settings.music_volume, settings.sound_volume, settings.game_speed), (0, 10, 70));
+        assert!(settings.fullscreen && settings.integer_scaling);
+        assert!(!settings.particles && !settings.popups);
+        assert_eq!(settings.bindings.keys, save.settings.bindings.keys);
+    }
+
+    #[test]
+    fn bad_setting_values_fall_back_or_clamp() {
+        let loaded = SaveData::parse("music_volume=99\ngame_speed=20\nbind_Jump=Escape\nunlocked_levels=0\nnonsense\n");
+
+        assert_eq!(loaded.settings.music_volume, 10);
+        assert_eq!(loaded.settings.game_speed, 50);
+        assert_eq!(loaded.settings.bindings.key(Action::Jump), KeyCode::Space);
+        assert_eq!(loaded.unlocked_levels, 1);
+    }
+
+    #[test]
+    fn continue_point_keeps_the_continues_spent() {
+        let mut save = SaveData::new();
+        save.progress = Some(Progress { level: 2, score: 4300, lives: 1, continues_used: 2 });
+
+        let progress = SaveData::parse(&save.to_text()).progress.unwrap();
+
+        assert_eq!((progress.level, progress.score, progress.lives, progress.continues_used), (2, 4300, 1, 2));
+        assert!(SaveData::parse(&SaveData::new().to_text()).progress.is_none());
+    }
+
+    #[test]
+    fn continue_points_from_older_saves_have_spent_none() {
+        let progress = SaveData::parse("continue=1,800,3\n").progress.unwrap();
+        assert_eq!((progress.level, progress.continues_used), (1, 0));
+
+        assert!(SaveData::parse("continue=1,lots,3\n").progress.is_none());
+    }
+
+    // Feeds events to the lifetime stats and then the achievements, in the
+    // order Game::dispatch_events does, and returns the ids unlocked
+    fn unlocked_by(achievements: &mut Achievements, stats: &mut LifetimeStats, events: &[GameEvent]) -> Vec<&'static str> {
+        let mut toasts = Toasts::new();
+        for event in events {
+            stats.observe(event);
+            achievements.observe(event, stats, &mut toasts);
+        }
+        ACHIEVEMENTS.iter()
+            .filter(|achievement| toasts.queue.iter().any(|(name, _)| *name == achievement.name))
+            .map(|achievement| achievement.id)
+            .collect()
+    }
+
+    fn completed(level: usize, time: f32, coins: usize) -> GameEvent {
+        GameEvent::LevelCompleted { level, time, par_time: 45.0, coins, total_coins: 10 }
+    }
+
+    fn started(level: usize) -> GameEvent {
+        GameEvent::LevelStarted { level, music: Music::Overworld }
+    }
+
+    #[test]
+    fn fiftieth_stomp_unlocks_once() {
+        let mut achievements = Achievements::new();
+        let mut stats = LifetimeStats::new();
+        let stomp = || GameEvent::EnemyStomped { at: Rect::new(0.0, 0.0, 24.0, 24.0), chain: 0 };
+
+        let first: Vec<GameEvent> = (0..49).map(|_| stomp()).collect();
+        assert!(unlocked_by(&mut achievements, &mut stats, &first).is_empty());
+        assert_eq!(unlocked_by(&mut achievements, &mut stats, &[stomp()]), ["stomp_50"]);
+        assert!(unlocked_by(&mut achievements, &mut stats, &[stomp()]).is_empty());
+    }
+
+    #[test]
+    fn level_rules_check_the_level_and_how_it_went() {
+        let mut stats = LifetimeStats::new();
+
+        let mut achievements = Achievements::new();
+        let events = [started(1), completed(1, 60.0, 0)];
+        assert!(unlocked_by(&mut achievements, &mut stats, &events).is_empty());
+
+        let events = [started(0), completed(0, 60.0, 0)];
+        assert_eq!(unlocked_by(&mut achievements, &mut stats, &events), ["no_coins_1_1"]);
+
+        let events = [started(0), completed(0, 40.0, 10)];
+        assert_eq!(unlocked_by(&mut achievements, &mut stats, &events), ["all_coins", "under_par"]);
+    }
+
+    #[test]
+    fn getting_hurt_spoils_no_damage_until_the_level_starts_again() {
+        let mut stats = LifetimeStats::new();
+        let mut achievements = Achievements::new();
+
+        let events = [started(2), GameEvent::PlayerHurt, completed(2, 60.0, 5)];
+        assert!(unlocked_by(&mut achievements, &mut stats, &events).is_empty());
+
+        let events = [started(2), completed(2, 60.0, 5)];
+        assert_eq!(unlocked_by(&mut achievements, &mut stats, &events), ["no_damage_1_3"]);
+    }
+
+    #[test]
+    fn unlocked_achievements_survive_a_save() {
+        let mut save = SaveData::new();
+        save.achievements.unlocked[1] = true;
+        save.achievements.unlocked[4] = true;
+
+        let loaded = SaveData::parse(&save.to_text());
+
+        assert_eq!(loaded.achievements.unlocked, [false, true, false, false, true]);
+    }
+
+    #[test]
+    fn falling_onto_an_enemy_stomps_it() {
+        let mut world = world_with_player(vec![Bundle::enemy(100.0, 100.0)], 100.0, 80.0, 100.0);
+        let mut player = Player::new();
+        let mut events = Vec::new();
+
+        let hurt = world.damage_system(&mut player, &mut events);
+
+        assert!(!hurt);
+        assert!(matches!(events[..], [GameEvent::EnemyStomped { chain: 0, .. }]));
+        assert_eq!(player.stomp_chain, 1);
+        assert!(world.hostile_entities.is_empty());
+        assert!(world.velocities[PLAYER].is_some_and(|velocity| velocity.y == -300.0));
+    }
+
+    #[test]
+    fn touching_two_enemies_hurts_once() {
+        let enemies = vec![Bundle::enemy(100.0, 100.0), Bundle::enemy(110.0, 100.0)];
+        let mut world = world_with_player(enemies, 100.0, 100.0, 0.0);
+        let mut player = Player::new();
+        let mut events = Vec::new();
+
+        let hurt = world.damage_system(&mut player, &mut events);
+
+        assert!(hurt);
+        assert!(events.is_empty());
+        assert_eq!(world.hostile_entities.len(), 2);
+    }
+
+    #[test]
+    fn touching_a_coin_collects_it_once() {
+        let mut world = world_with_player(vec![Bundle::coin(100.0, 100.0)], 100.0, 100.0, 0.0);
+        let mut events = Vec::new();
+        let area = world.rect(PLAYER).unwrap();
+
+        world.pickup_system(area, &mut events);
+        world.pickup_system(area, &mut events);
+
+        assert!(matches!(events[..], [GameEvent::CoinCollected { .. }]));
+        assert_eq!(world.coins_left(), 0);
+    }
+
+    #[test]
+    fn lifetime_stats_survive_a_save() {
+        let mut save = SaveData::new();
+        let events = [
+            started(0),
+            GameEvent::Jumped,
+            GameEvent::CoinCollected { at: Rect::new(0.0, 0.0, 16.0, 16.0) },
+            GameEvent::EnemyStomped { at: Rect::new(0.0, 0.0, 24.0, 24.0), chain: 0 },
+            GameEvent::Moved { distance: 12.5 },
+            GameEvent::Ticked { dt: 0.5 },
+            GameEvent::PlayerDied { cause: DeathCause::Crushed, x: 0.0, y: 0.0 },
+            started(0),
+            completed(0, 42.5, 3),
+            completed(0, 50.0, 3),
+        ];
+        for event in &events {
+            save.stats.observe(event);
+        }
+
+        let stats = SaveData::parse(&save.to_text()).stats;
+
+        assert_eq!((stats.jumps, stats.coins, stats.stomps), (1, 1, 1));
+        assert_eq!((stats.distance, stats.play_time), (12.5, 0.5));
+        assert_eq!(stats.deaths, [0, 0, 1]);
+        assert_eq!(stats.best_times, [Some(42.5), None, None]);
+        assert_eq!(stats.attempts, [2, 0, 0]);
+    }
+
+    #[test]
+    fn death_records_survive_a_round_trip() {
+        let record = DeathRecord { level: 2, room: 1, x: 123.5, y: -40.0, cause: DeathCause::Crushed };
+
+        let parsed = DeathRecord::parse(&record.to_json()).unwrap();
+
+        assert_eq!((parsed.level, parsed.room, parsed.x, parsed.y), (2, 1, 123.5, -40.0));
+        assert!(parsed.cause == DeathCause::Crushed);
+        assert!(DeathRecord::parse("not a record").is_none());
+    }
+
+    #[test]
+    fn heatmap_csv_counts_deaths_per_cell_worst_first() {
+        let death = |x, y, cause| DeathRecord { level: 0, room: 0, x, y, cause };
+        let deaths = [
+            death(100.0, 40.0, DeathCause::Crushed),
+            death(10.0, 10.0, DeathCause::Enemy),
+            death(-5.0, 700.0, DeathCause::Fall),
+            death(20.0, 31.9, DeathCause::Fall),
+        ];
+
+        assert_eq!(heatmap_csv(&deaths), "\
+            x,y,deaths,enemy,fall,crushed\n\
+            0,0,2,1,1,0\n\
+            -32,672,1,0,1,0\n\
+            96,32,1,0,0,1\n");
+        assert_eq!(heatmap_csv(&[]), "x,y,deaths,enemy,fall,crushed\n");
+    }
+
+    #[test]
+    fn grid_reports_each_index_once() {
+        // The first rectangle spans many cells, the second only one
+        let rects = [Rect::new(0.0, 0.0, 100.0, 100.0), Rect::new(50.0, 50.0, 5.0, 5.0)];
+        let mut grid = SpatialGrid::new(&rects, 10.0);
+
+        let mut found: Vec<usize> = grid.query(Rect::new(0.0, 0.0, 100.0, 100.0)).collect();
+        found.sort();
+        assert_eq!(found, [0, 1]);
+        assert_eq!(grid.query(Rect::new(90.0, 90.0, 5.0, 5.0)).collect::<Vec<_>>(), [0]);
+
+        grid.remove(0, rects[0]);
+        assert_eq!(grid.query(Rect::new(0.0, 0.0, 100.0, 100.0)).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn removed_bricks_leave_other_indices_alone() {
+        let mut terrain = Terrain::new(vec![Platform::brick(0.0, 0.0), Platform::brick(32.0, 0.0)]);
+
+        assert!(terrain.remove(0).is_some());
+        assert!(terrain.remove(0).is_none());
+
+        let near: Vec<usize> = terrain.near(Rect::new(0.0, 0.0, 64.0, 32.0)).map(|(index, _)| index).collect();
+        assert_eq!(near, [1]);
+    }
+
+    #[test]
+    fn pickups_are_found_through_the_grid_only_once() {
+        // The middle coin sits across four 128px cells
+        let coins = vec![Bundle::coin(0.0, 0.0), Bundle::coin(120.0, 120.0), Bundle::coin(250.0, 250.0)];
+        let mut world = world_with_player(coins, 110.0, 110.0, 0.0);
+        let mut events = Vec::new();
+
+        world.pickup_system(Rect::new(0.0, 0.0, 400.0, 400.0), &mut events);
+        world.pickup_system(Rect::new(0.0, 0.0, 400.0, 400.0), &mut events);
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(world.coins_left(), 0);
+    }
+
+    #[test]
+    fn enemies_land_and_patrol_between_platform_edges() {
+        let terrain = Terrain::new(vec![Platform::new(0.0, 500.0, 200.0, 20.0)]);
+        let mut world = World::new(vec![Bundle::enemy(80.0, 400.0)]);
+        let enemy = 1;
+        let (mut left, mut right) = (f32::MAX, f32::MIN);
+
+        for _ in 0..600 {
+            world.update(1.0 / 60.0, &terrain);
+            let at = world.rect(enemy).unwrap();
+            left = left.min(at.x);
+            right = right.max(at.x + at.w);
+        }
+
+        assert_eq!(world.rect(enemy).unwrap().bottom(), 500.0);
+        assert!((-1.0..10.0).contains(&left), "{}", left);
+        assert!((190.0..=201.0).contains(&right), "{}", right);
+    }
+
+    #[test]
+    fn entities_without_velocity_stay_put() {
+        let terrain = Terrain::new(vec![Platform::new(0.0, 500.0, 200.0, 20.0)]);
+        let mut world = World::new(vec![Bundle::coin(50.0, 300.0), Bundle::powerup(100.0, 200.0, PowerUpType::JumpBoost)]);
+
+        for _ in 0..60 {
+            world.update(1.0 / 60.0, &terrain);
+        }
+
+        assert_eq!(world.rect(1).unwrap().point(), vec2(50.0, 300.0));
+        assert_eq!(world.rect(2).unwrap().point(), vec2(100.0, 200.0));
+    }
+
+    #[test]
+    fn player_lands_on_ground_and_bumps_bricks() {
+        let terrain = Terrain::new(vec![Platform::new(0.0, 500.0, 400.0, 20.0), Platform::brick(100.0, 300.0)]);
+        let mut world = world_with_player(Vec::new(), 100.0, 400.0, 0.0);
+        for _ in 0..120 {
+            world.update(1.0 / 60.0, &terrain);
+        }
+        assert!(world.on_ground(PLAYER));
+        assert_eq!(world.rect(PLAYER).unwrap().bottom(), 500.0);
+
+        world.velocities[PLAYER] = Some(Velocity { x: 0.0, y: -500.0 });
+        for _ in 0..30 {
+            world.update(1.0 / 60.0, &terrain);
+            if world.bodies[PLAYER].is_some_and(|body| body.bumped.is_some()) {
+                break;
+            }
+        }
+        assert_eq!(world.bodies[PLAYER].and_then(|body| body.bumped), Some(1));
+        assert_eq!(world.rect(PLAYER).unwrap().y, 332.0);
+    }
+
+    #[test]
+    fn stomped_enemies_are_removed_once_their_squash_plays_out() {
+        let mut world = world_with_player(vec![Bundle::enemy(100.0, 100.0)], 100.0, 80.0, 100.0);
+        let terrain = Terrain::new(Vec::new());
+        world.damage_system(&mut Player::new(), &mut Vec::new());
+        assert!(world.corpses[1].is_some());
+
+        world.animation_system(0.25);
+        assert!(world.rect(1).is_some());
+        world.animation_system(0.5);
+        assert!(world.rect(1).is_none());
+        // The player has no clip that ends, so it's never mistaken for one
+        world.update(1.0, &terrain);
+        assert!(world.rect(PLAYER).is_some());
+    }
+
+    #[test]
+    fn player_entity_moves_between_worlds_whole() {
+        let mut main = world_with_player(vec![Bundle::enemy(300.0, 100.0)], 100.0, 80.0, 250.0);
+        let mut bonus = World::new(vec![Bundle::coin(0.0, 0.0)]);
+
+        let player = main.take(PLAYER).unwrap();
+        bonus.insert(PLAYER, player);
+
+        assert!(main.rect(PLAYER).is_none());
+        assert_eq!(main.hostile_entities, [1]);
+        assert_eq!(bonus.rect(PLAYER), Some(Rect::new(100.0, 80.0, 32.0, 32.0)));
+        assert!(bonus.velocities[PLAYER].is_some_and(|velocity| velocity.y == 250.0));
+        assert!(bonus.bodies[PLAYER].is_some() && bonus.hostile_entities.is_empty());
     }
 }